@@ -0,0 +1,559 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    error::{CompileError, MovaError, Result},
+    interpreter::{NativeFunction, Value, native_builtin},
+    parser::{
+        expression::{Arguments, Expression},
+        node::Node,
+        statement::Statement,
+    },
+};
+
+/// Where a variable lives while the bytecode runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variable {
+    /// A top-level variable, or one declared in a block outside of any function
+    Global(usize),
+    /// A parameter or block variable of the function being executed
+    Local(usize),
+}
+
+/// A single step of the stack machine in `vm`.
+///
+/// The stack holds `Option<Value>`, where `None` is the missing result of a statement.
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    /// Pushes a number or boolean
+    Constant(Value),
+    /// Pushes the result of a statement or of an `if` without `else`
+    Nothing,
+    Pop,
+    Duplicate,
+    Load(Variable),
+    /// Pops a value into a variable declared by `let`
+    Declare {
+        variable: Variable,
+        is_mutable: bool,
+    },
+    /// Pops a value into an existing variable, which must be mutable or still uninitialized
+    Store(Variable),
+    Unary(&'static str),
+    Binary(Rc<String>),
+    Jump(usize),
+    /// Pops a condition and jumps if it is false
+    JumpIfFalse(usize),
+    /// Jumps if the top of the stack is `false` without popping it, so `&&` can skip its right
+    /// operand
+    SkipIfFalse(usize),
+    /// Spends one loop iteration, failing once the budget is exhausted or on interrupt
+    Iterate,
+    /// Marks a top-level function as declared, which makes it callable
+    Define(usize),
+    Call {
+        function: usize,
+        arguments: usize,
+    },
+    CallNative {
+        native: usize,
+        arguments: usize,
+    },
+    Print(usize),
+    /// Fails with a call to a function that no declaration provides
+    Undefined(Rc<String>),
+    /// Pops the result and returns it to the caller
+    Return,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: Rc<String>,
+    pub parameters: usize,
+    /// Names of the local slots, starting with the parameters
+    pub locals: Vec<Rc<String>>,
+    pub code: Vec<Instruction>,
+}
+
+/// A compiled program
+#[derive(Debug)]
+pub struct Bytecode {
+    pub main: Function,
+    pub functions: Vec<Function>,
+    pub natives: Vec<NativeFunction>,
+    /// Names of the global slots
+    pub globals: Vec<Rc<String>>,
+}
+
+/// Code generated for one function, or for the top level
+struct Chunk {
+    code: Vec<Instruction>,
+    /// Variables of the enclosing blocks, innermost last
+    scopes: Vec<HashMap<Rc<String>, Variable>>,
+    locals: Vec<Rc<String>>,
+    is_main: bool,
+}
+
+impl Chunk {
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.code.push(instruction);
+        self.code.len() - 1
+    }
+
+    /// Points the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let next = self.code.len();
+        if let Instruction::Jump(target)
+        | Instruction::JumpIfFalse(target)
+        | Instruction::SkipIfFalse(target) = &mut self.code[at]
+        {
+            *target = next;
+        }
+    }
+}
+
+struct Compiler<'a> {
+    /// Top-level function declarations, indexed like `Bytecode::functions`
+    declarations: Vec<&'a Statement>,
+    functions: HashMap<Rc<String>, usize>,
+    /// Names declared with `let` at the top level
+    variables: HashSet<Rc<String>>,
+    /// Global slots of top-level variables, which like the global scope are found by name
+    globals: HashMap<Rc<String>, usize>,
+    global_names: Vec<Rc<String>>,
+    natives: Vec<NativeFunction>,
+}
+
+fn unsupported(feature: &str) -> MovaError {
+    MovaError::Compile(CompileError::Unsupported(feature.to_string()))
+}
+
+/// Lowers a parsed program into bytecode for `vm::execute`.
+///
+/// Only numbers and booleans, variables, operators, conditionals, loops and calls to
+/// top-level functions or builtins are supported so far.
+pub fn compile(program: &Node) -> Result<Bytecode> {
+    let nodes = match program {
+        Node::Expression(expression) => match &**expression {
+            Expression::Program(nodes) => nodes.as_ref(),
+            _ => std::slice::from_ref(program),
+        },
+        Node::Statement(_) => std::slice::from_ref(program),
+    };
+
+    let mut compiler = Compiler {
+        declarations: Vec::new(),
+        functions: HashMap::new(),
+        variables: HashSet::new(),
+        globals: HashMap::new(),
+        global_names: Vec::new(),
+        natives: Vec::new(),
+    };
+    for node in nodes {
+        match node {
+            Node::Statement(statement) => match &**statement {
+                Statement::Variable { name, .. } => {
+                    compiler.variables.insert(Rc::clone(name));
+                }
+                Statement::Function { name, .. } => {
+                    let index = compiler.declarations.len();
+                    if compiler.functions.insert(Rc::clone(name), index).is_some() {
+                        return Err(unsupported("functions declared more than once"));
+                    }
+                    compiler.declarations.push(statement);
+                }
+                _ => {}
+            },
+            Node::Expression(_) => {}
+        }
+    }
+    // Functions and variables share one namespace, which would make calls depend on
+    // the order of declarations
+    if compiler
+        .functions
+        .keys()
+        .any(|name| compiler.variables.contains(name))
+    {
+        return Err(unsupported("variables named like functions"));
+    }
+
+    let mut main = Chunk {
+        code: Vec::new(),
+        scopes: Vec::new(),
+        locals: Vec::new(),
+        is_main: true,
+    };
+    compiler.compile_nodes(&mut main, nodes)?;
+    main.emit(Instruction::Return);
+
+    let mut functions = Vec::with_capacity(compiler.declarations.len());
+    for index in 0..compiler.declarations.len() {
+        functions.push(compiler.compile_function(index)?);
+    }
+
+    Ok(Bytecode {
+        main: Function {
+            name: Rc::new("main".to_string()),
+            parameters: 0,
+            locals: main.locals,
+            code: main.code,
+        },
+        functions,
+        natives: compiler.natives,
+        globals: compiler.global_names,
+    })
+}
+
+impl Compiler<'_> {
+    fn compile_function(&mut self, index: usize) -> Result<Function> {
+        let Statement::Function {
+            name,
+            parameters,
+            body,
+            ..
+        } = self.declarations[index]
+        else {
+            return Err(unsupported("declarations other than functions"));
+        };
+        if parameters
+            .iter()
+            .any(|parameter| parameter.default.is_some())
+        {
+            return Err(unsupported("default parameter values"));
+        }
+
+        let mut chunk = Chunk {
+            code: Vec::new(),
+            scopes: vec![HashMap::new()],
+            locals: Vec::new(),
+            is_main: false,
+        };
+        for parameter in parameters.iter() {
+            self.declare(&mut chunk, &parameter.name);
+        }
+        self.compile_expression(&mut chunk, body)?;
+        chunk.emit(Instruction::Return);
+
+        Ok(Function {
+            name: Rc::clone(name),
+            parameters: parameters.len(),
+            locals: chunk.locals,
+            code: chunk.code,
+        })
+    }
+
+    fn global(&mut self, name: &Rc<String>) -> Variable {
+        let index = *self.globals.entry(Rc::clone(name)).or_insert_with(|| {
+            self.global_names.push(Rc::clone(name));
+            self.global_names.len() - 1
+        });
+        Variable::Global(index)
+    }
+
+    /// Allocates the slot for a `let` or a parameter in the innermost scope
+    fn declare(&mut self, chunk: &mut Chunk, name: &Rc<String>) -> Variable {
+        let variable = match (chunk.scopes.is_empty(), chunk.is_main) {
+            (true, _) => return self.global(name),
+            (false, true) => {
+                self.global_names.push(Rc::clone(name));
+                Variable::Global(self.global_names.len() - 1)
+            }
+            (false, false) => {
+                chunk.locals.push(Rc::clone(name));
+                Variable::Local(chunk.locals.len() - 1)
+            }
+        };
+        if let Some(scope) = chunk.scopes.last_mut() {
+            scope.insert(Rc::clone(name), variable);
+        }
+        variable
+    }
+
+    /// Finds the variable a name refers to, which is global unless a block declares it
+    fn resolve(&mut self, chunk: &Chunk, name: &Rc<String>) -> Result<Variable> {
+        if let Some(variable) = chunk.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            return Ok(*variable);
+        }
+        if self.functions.contains_key(name) {
+            return Err(unsupported("functions used as values"));
+        }
+        Ok(self.global(name))
+    }
+
+    /// Compiles nodes so that exactly the result of the last one is left on the stack
+    fn compile_nodes(&mut self, chunk: &mut Chunk, nodes: &[Node]) -> Result<()> {
+        let mut has_result = false;
+        for node in nodes {
+            if has_result {
+                chunk.emit(Instruction::Pop);
+            }
+            has_result = match node {
+                Node::Expression(expression) => {
+                    self.compile_expression(chunk, expression)?;
+                    true
+                }
+                Node::Statement(statement) => {
+                    self.compile_statement(chunk, statement)?;
+                    false
+                }
+            };
+        }
+        if !has_result {
+            chunk.emit(Instruction::Nothing);
+        }
+        Ok(())
+    }
+
+    /// Compiles nodes in a scope of their own, like a block
+    fn compile_scoped(&mut self, chunk: &mut Chunk, nodes: &[Node]) -> Result<()> {
+        chunk.scopes.push(HashMap::new());
+        let result = self.compile_nodes(chunk, nodes);
+        chunk.scopes.pop();
+        result
+    }
+
+    fn compile_statement(&mut self, chunk: &mut Chunk, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Variable {
+                name,
+                value,
+                is_mutable,
+            } => {
+                match value {
+                    Some(value) => self.compile_expression(chunk, value)?,
+                    None => {
+                        chunk.emit(Instruction::Constant(Value::Uninitialized));
+                    }
+                }
+                // Declared after the value, which may still read a shadowed variable
+                let variable = self.declare(chunk, name);
+                chunk.emit(Instruction::Declare {
+                    variable,
+                    is_mutable: *is_mutable,
+                });
+            }
+            Statement::Assignment { name, value } => {
+                self.compile_expression(chunk, value)?;
+                let variable = self.resolve(chunk, name)?;
+                chunk.emit(Instruction::Store(variable));
+            }
+            Statement::ChainedAssignment { names, value } => {
+                self.compile_expression(chunk, value)?;
+                for (index, name) in names.iter().enumerate().rev() {
+                    if index > 0 {
+                        chunk.emit(Instruction::Duplicate);
+                    }
+                    let variable = self.resolve(chunk, name)?;
+                    chunk.emit(Instruction::Store(variable));
+                }
+            }
+            Statement::Function { name, .. } => {
+                let index = match self.functions.get(name) {
+                    Some(index) if chunk.is_main && chunk.scopes.is_empty() => *index,
+                    _ => return Err(unsupported("functions declared inside blocks")),
+                };
+                chunk.emit(Instruction::Define(index));
+            }
+            Statement::DereferenceAssignment { .. } => {
+                return Err(unsupported("assignments through references"));
+            }
+            Statement::Destructure { .. } => return Err(unsupported("tuples")),
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, chunk: &mut Chunk, expression: &Expression) -> Result<()> {
+        match expression {
+            Expression::Number(n) => {
+                chunk.emit(Instruction::Constant(Value::Number(*n)));
+            }
+            Expression::Boolean(b) => {
+                chunk.emit(Instruction::Constant(Value::Boolean(*b)));
+            }
+            Expression::Identifier(name) => {
+                let variable = self.resolve(chunk, name)?;
+                chunk.emit(Instruction::Load(variable));
+            }
+            Expression::BinaryExpression {
+                operator,
+                left,
+                right,
+            } => {
+                self.compile_expression(chunk, left)?;
+                let skip =
+                    (operator.as_str() == "&&").then(|| chunk.emit(Instruction::SkipIfFalse(0)));
+                self.compile_expression(chunk, right)?;
+                chunk.emit(Instruction::Binary(Rc::clone(operator)));
+                if let Some(skip) = skip {
+                    chunk.patch(skip);
+                }
+            }
+            Expression::Call { name, arguments } => self.compile_call(chunk, name, arguments)?,
+            Expression::BitwiseNot(operand) => {
+                self.compile_expression(chunk, operand)?;
+                chunk.emit(Instruction::Unary("~"));
+            }
+            Expression::Negate(operand) => {
+                self.compile_expression(chunk, operand)?;
+                chunk.emit(Instruction::Unary("-"));
+            }
+            Expression::Do(inner) => {
+                self.compile_scoped(chunk, &[Node::Expression(Rc::clone(inner))])?;
+            }
+            Expression::Block(nodes) => self.compile_scoped(chunk, nodes)?,
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.compile_expression(chunk, condition)?;
+                let to_alternative = chunk.emit(Instruction::JumpIfFalse(0));
+                self.compile_expression(chunk, consequence)?;
+                let to_end = chunk.emit(Instruction::Jump(0));
+                chunk.patch(to_alternative);
+                match alternative {
+                    Some(alternative) => self.compile_expression(chunk, alternative)?,
+                    None => {
+                        chunk.emit(Instruction::Nothing);
+                    }
+                }
+                chunk.patch(to_end);
+            }
+            Expression::While { condition, body } => {
+                // The loop results in its last iteration's value, or nothing if there was none
+                chunk.emit(Instruction::Nothing);
+                let start = chunk.code.len();
+                self.compile_expression(chunk, condition)?;
+                let to_end = chunk.emit(Instruction::JumpIfFalse(0));
+                chunk.emit(Instruction::Pop);
+                chunk.emit(Instruction::Iterate);
+                self.compile_expression(chunk, body)?;
+                chunk.emit(Instruction::Jump(start));
+                chunk.patch(to_end);
+            }
+            Expression::Program(nodes) => self.compile_nodes(chunk, nodes)?,
+            Expression::Reference { .. } | Expression::Dereference(_) => {
+                return Err(unsupported("references"));
+            }
+            Expression::Tuple(_) => return Err(unsupported("tuples")),
+            Expression::Index { .. } => return Err(unsupported("indexing")),
+            Expression::Return(_) => return Err(unsupported("return expressions")),
+        }
+        Ok(())
+    }
+
+    fn compile_call(
+        &mut self,
+        chunk: &mut Chunk,
+        name: &Rc<String>,
+        arguments: &Arguments,
+    ) -> Result<()> {
+        if !arguments.named.is_empty() {
+            return Err(unsupported("named arguments"));
+        }
+        let is_variable = chunk.scopes.iter().any(|scope| scope.contains_key(name))
+            || self.variables.contains(name);
+        if is_variable {
+            return Err(unsupported("calls to variables"));
+        }
+
+        for argument in &arguments.positional {
+            self.compile_expression(chunk, argument)?;
+        }
+        let count = arguments.positional.len();
+
+        let instruction = if let Some(function) = self.functions.get(name) {
+            Instruction::Call {
+                function: *function,
+                arguments: count,
+            }
+        } else if name.as_str() == "print" {
+            Instruction::Print(count)
+        } else if let Some(native) = self.native(name) {
+            Instruction::CallNative {
+                native,
+                arguments: count,
+            }
+        } else if matches!(name.as_str(), "drop" | "clone" | "range" | "input") {
+            return Err(unsupported(&format!("calls to '{name}'")));
+        } else {
+            Instruction::Undefined(Rc::clone(name))
+        };
+        chunk.emit(instruction);
+        Ok(())
+    }
+
+    /// Index of the native builtin called `name`, added on its first use
+    fn native(&mut self, name: &str) -> Option<usize> {
+        if let Some(index) = self.natives.iter().position(|native| native.name == name) {
+            return Some(index);
+        }
+        self.natives.push(native_builtin(name)?);
+        Some(self.natives.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::tokenize, parser::parse};
+
+    fn compile_source(input: &str) -> Result<Bytecode> {
+        compile(&parse(tokenize(input)?)?)
+    }
+
+    #[test]
+    fn it_resolves_top_level_variables_by_name() -> Result<()> {
+        let bytecode = compile_source("let x = 1; let x = x + 1; fn f() = x")?;
+        assert_eq!(bytecode.globals.len(), 1);
+        assert!(matches!(
+            bytecode.functions[0].code[0],
+            Instruction::Load(Variable::Global(0))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn it_gives_block_variables_slots_of_their_own() -> Result<()> {
+        let bytecode = compile_source("fn f(x) = { let x = x + 1; { let x = 2; x } }")?;
+        assert_eq!(bytecode.functions[0].parameters, 1);
+        assert_eq!(bytecode.functions[0].locals.len(), 3);
+        assert!(bytecode.globals.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_patches_jumps_past_the_branches() -> Result<()> {
+        let bytecode = compile_source("if true { 1 } else { 2 }")?;
+        let code = &bytecode.main.code;
+        assert!(matches!(code[1], Instruction::JumpIfFalse(4)));
+        assert!(matches!(code[3], Instruction::Jump(5)));
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_unsupported_features() {
+        let inputs = [
+            "let x = 1; &x",
+            "(1, 2)",
+            "fn f(x = 1) = x",
+            "fn f(x) = x; f(x = 1)",
+            "fn f() = 1; let g = f",
+            "let x = 1; x(2)",
+            "{ fn f() = 1 }",
+            "range(0, 2)",
+            "fn f() = { return 1 }",
+        ];
+        for input in inputs {
+            assert!(
+                matches!(
+                    compile_source(input),
+                    Err(MovaError::Compile(CompileError::Unsupported(_)))
+                ),
+                "{input}"
+            );
+        }
+    }
+}
@@ -1,9 +1,12 @@
 use std::fmt;
 use thiserror::Error;
 
+use crate::{interpreter::Value, lexer::Token};
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Position {
     pub line: usize,
+    /// 1-based column within the line
     pub character: usize,
 }
 
@@ -17,10 +20,92 @@ impl fmt::Display for Position {
 pub enum MovaError {
     #[error("Lexer error at {position}: Unexpected character: '{character}'")]
     Lexer { character: char, position: Position },
-    #[error("Parser error: {0}")]
-    Parser(#[from] ParserError),
+    #[error("Lexer error at {position}: Unterminated block comment")]
+    UnterminatedComment { position: Position },
+    #[error("Lexer error at {position}: Indentation does not match any enclosing line")]
+    InconsistentIndentation { position: Position },
+    /// Located at the token where parsing failed, when the tokens carry spans
+    #[error("Parser error{}: {error}", at(.position))]
+    Parser {
+        error: ParserError,
+        position: Option<Position>,
+    },
     #[error("Runtime error: {0}")]
     Runtime(#[from] RuntimeError),
+    #[error("Compile error: {0}")]
+    Compile(#[from] CompileError),
+    #[error("{error}\n  in {}", frames.join(" -> "))]
+    Backtrace {
+        error: Box<MovaError>,
+        frames: Vec<String>,
+    },
+    /// Carries the value of a `return` out to the function call it leaves, so it only
+    /// reaches the caller when `return` is used outside of any function
+    #[error("Runtime error: 'return' used outside of a function")]
+    Return(Option<Value>),
+}
+
+/// A problem found by a static check that does not stop the program from running
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum MovaWarning {
+    #[error("Warning: Unreachable code after return: {0}")]
+    UnreachableCode(String),
+}
+
+/// ` at line:character` for an error whose position is known
+fn at(position: &Option<Position>) -> String {
+    position
+        .as_ref()
+        .map_or_else(String::new, |position| format!(" at {position}"))
+}
+
+fn on_line(line: &Option<usize>) -> String {
+    line.map_or_else(String::new, |line| format!(" opened on line {line}"))
+}
+
+impl From<ParserError> for MovaError {
+    fn from(error: ParserError) -> Self {
+        MovaError::Parser {
+            error,
+            position: None,
+        }
+    }
+}
+
+impl MovaError {
+    /// Where in the source the error occurred, if that is known
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            MovaError::Lexer { position, .. }
+            | MovaError::UnterminatedComment { position }
+            | MovaError::InconsistentIndentation { position } => Some(position.clone()),
+            MovaError::Parser { position, .. } => position.clone(),
+            MovaError::Backtrace { error, .. } => error.position(),
+            MovaError::Runtime(_) | MovaError::Compile(_) | MovaError::Return(_) => None,
+        }
+    }
+
+    /// Records that the error unwound through a call to `function`
+    pub fn in_frame(self, function: &str) -> Self {
+        match self {
+            MovaError::Backtrace { error, mut frames } => {
+                frames.insert(0, function.to_string());
+                MovaError::Backtrace { error, frames }
+            }
+            error => MovaError::Backtrace {
+                error: Box::new(error),
+                frames: vec![function.to_string()],
+            },
+        }
+    }
+
+    /// Names of the functions that were being called when the error occurred, outermost first
+    pub fn frames(&self) -> &[String] {
+        match self {
+            MovaError::Backtrace { frames, .. } => frames,
+            _ => &[],
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -31,48 +116,123 @@ pub enum ParserError {
     ExpectedCommaOrArgumentListToBeClosed,
     #[error("Expected identifier to be called but found {0}")]
     ExpectedIdentifierToBeCalled(String),
-    #[error("Expected ')' but found {0}")]
-    ExpectedClosingParenthesis(String),
-    #[error("Expected ')' but found end of input")]
-    ExpectedClosingParenthesisButFoundEndOfInput,
+    #[error("Expected argument list after method name '{0}'")]
+    ExpectedArgumentListAfterMethodName(String),
+    #[error("Expected ')' but found {found}")]
+    UnbalancedParen { found: Box<Token> },
     #[error("Invalid number: {0}")]
     InvalidNumber(String),
-    #[error("Unexpected token found: {0}")]
-    UnexpectedToken(String),
+    #[error("Numeric literal out of range for i32: {0}")]
+    NumericLiteralOutOfRange(String),
+    #[error("Expected {expected} but found {found}")]
+    UnexpectedToken {
+        found: Box<Token>,
+        expected: &'static str,
+    },
     #[error("Unexpected end of input")]
     UnexpectedEndOfInput,
-    #[error("Expected block to be closed")]
-    ExpectedBlockToBeClosed,
-    #[error("Expected identifier but got: {0}")]
-    ExpectedIdentifierButGot(String),
+    /// Carries the line the block was opened on, when the tokens carry spans
+    #[error("Expected block{} to be closed", on_line(.opened_on))]
+    UnbalancedBrace { opened_on: Option<usize> },
+    #[error("'{0}' is a reserved word and cannot be used as a name")]
+    ReservedWordAsIdentifier(String),
     #[error("Expected identifier after `let` keyword")]
     ExpectedIdentifierAfterLet,
-    #[error("Expected assignment after identifier")]
-    ExpectedAssignmentAfterIdentifier,
     #[error("Expected function name after `fn` keyword")]
     ExpectedFunctionName,
     #[error("Expected parameter list after function name")]
     ExpectedParameterList,
     #[error("Expected parameter list to be closed")]
     ExpectedParameterListToBeClosed,
+    #[error("Expected a default value for parameter '{0}', since an earlier parameter has one")]
+    ExpectedDefaultForParameter(String),
+    #[error("Function body must be an expression or block")]
+    FunctionBodyMustBeExpressionOrBlock,
+    #[error("Positional arguments must come before named arguments")]
+    PositionalArgumentAfterNamed,
+    #[error("Cannot assign to '{0}'")]
+    InvalidAssignmentTarget(String),
     #[error("Expected assignment before function body")]
     ExpectedAssignmentBeforeFunctionBody,
     #[error("Unexpected keyword found: {0}")]
     UnexpectedKeyword(String),
+    #[error("Maximum nesting depth exceeded")]
+    MaximumNestingDepthExceeded,
+    #[error("A call cannot pass more than {0} arguments")]
+    TooManyArguments(usize),
+    #[error("A function cannot declare more than {0} parameters")]
+    TooManyParameters(usize),
 }
 
 #[derive(Debug, Error)]
 pub enum RuntimeError {
     #[error("Division by zero")]
     DivisionByZero,
-    #[error("Unexpected operator '{operator}' for operands '{left}' and '{right}'")]
-    UnexpectedOperator { operator: String, left: String, right: String },
+    #[error("Arithmetic overflow in '{left} {operator} {right}'")]
+    ArithmeticOverflow {
+        operator: String,
+        left: i32,
+        right: i32,
+    },
+    #[error("Shift amount out of range: {0}")]
+    ShiftOutOfRange(i32),
+    #[error("Operator '{operator}' is not defined for {operand}")]
+    UnaryOperatorNotDefined {
+        operator: &'static str,
+        operand: &'static str,
+    },
+    #[error("Operator '{operator}' is not defined for {left} and {right}")]
+    OperatorNotDefined {
+        operator: String,
+        left: &'static str,
+        right: &'static str,
+    },
+    #[error("Unknown operator '{0}'")]
+    UnknownOperator(String),
+    #[error("Cannot compare '{left}' with '{right}'")]
+    CannotCompare { left: String, right: String },
     #[error("Expected {expected} arguments but received {received}")]
     InvalidArgumentCount { expected: usize, received: usize },
+    #[error("Expected at least {minimum} arguments but received {received}")]
+    TooFewArguments { minimum: usize, received: usize },
+    #[error("Expected at most {maximum} arguments but received {received}")]
+    TooManyArguments { maximum: usize, received: usize },
+    #[error("'{function}' has no parameter named '{argument}'")]
+    UnknownArgument { function: String, argument: String },
+    #[error("Argument '{0}' is given more than once")]
+    DuplicateArgument(String),
+    #[error("Missing argument for parameter '{0}'")]
+    MissingArgument(String),
+    #[error("'{0}' does not accept named arguments")]
+    NamedArgumentsNotSupported(String),
+    #[error("Interrupted")]
+    Interrupted,
+    #[error("Maximum call depth of {0} exceeded")]
+    CallDepthExceeded(usize),
+    #[error("Range of {length} elements exceeds the limit of {limit}")]
+    RangeTooLong { length: usize, limit: usize },
+    #[error("Index {index} is out of bounds for an array of {length} elements")]
+    IndexOutOfBounds { index: i32, length: usize },
+    #[error("Cannot index into a {0}")]
+    NotIndexable(&'static str),
+    #[error("Expected a number as index but received '{0}'")]
+    IndexMustBeNumber(String),
+    #[error("Cannot pop from an empty array")]
+    PopFromEmptyArray,
+    #[error("An array cannot hold more than {} elements", i32::MAX)]
+    ArrayTooLong,
+    #[error("Unable to read input: {0}")]
+    UnableToReadInput(String),
+    #[error("Reached the end of input")]
+    EndOfInput,
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
     #[error("Expected expression, but received statement as argument")]
     ExpectedExpressionAsArgument,
-    #[error("'{0}' is not callable")]
-    NotCallable(String),
+    #[error("Call to undefined function '{0}'")]
+    CallToUndefinedFunction(String),
+    #[error("'{name}' is a {kind} and is not callable")]
+    NotCallable { name: String, kind: &'static str },
     #[error("Expression cannot be referenced")]
     ExpressionCannotBeReferenced,
     #[error("Reference target yielded no value")]
@@ -97,26 +257,38 @@ pub enum RuntimeError {
     CannotAssignToMutablyBorrowedVariable(String),
     #[error("Cannot assign to immutable variable '{0}'")]
     CannotAssignToImmutableVariable(String),
+    #[error("Cannot assign '{0}' to several variables, since it would be moved more than once")]
+    CannotAssignToSeveralVariables(String),
     #[error("Assignment value yielded no value")]
     AssignmentValueYieldedNoValue,
     #[error("Condition yielded no value")]
     ConditionYieldedNoValue,
     #[error("Condition must be a boolean")]
     ConditionMustBeBoolean,
+    #[error("Iteration limit exceeded")]
+    IterationLimitExceeded,
     #[error("Unable to resolve {0}")]
     UnableToResolve(String),
+    #[error("Unable to resolve {0} because its enclosing scope no longer exists")]
+    ParentScopeDropped(String),
+    #[error("Cannot shadow '{0}' while it is borrowed")]
+    CannotShadowBorrowedVariable(String),
     #[error("Variable '{0}' already exists")]
     VariableAlreadyExists(String),
     #[error("Unable to use '{0}' because it is moved")]
     UnableToUseBecauseMoved(String),
+    #[error("Use of possibly-uninitialized variable '{0}'")]
+    UseOfPossiblyUninitializedVariable(String),
     #[error("Unable to use '{0}' because it is deallocated")]
     UnableToUseBecauseDeallocated(String),
-    #[error("Unable to mutate '{0}' because it is immutably borrowed")]
-    UnableToMutateBecauseImmutablyBorrowed(String),
-    #[error("Unable to mutate '{0}' because it is mutably borrowed")]
-    UnableToMutateBecauseMutablyBorrowed(String),
+    #[error("Unable to move '{name}' because it is borrowed (active borrows: {borrows})")]
+    UnableToMoveBecauseBorrowed { name: String, borrows: usize },
+    #[error("Unable to use '{0}' because it is mutably borrowed")]
+    UnableToUseBecauseMutablyBorrowed(String),
     #[error("Unable to borrow value because it is moved")]
     UnableToBorrowBecauseMoved,
+    #[error("Unable to borrow value because it is uninitialized")]
+    UnableToBorrowBecauseUninitialized,
     #[error("Unable to borrow value because it is deallocated")]
     UnableToBorrowBecauseDeallocated,
     #[error("Unable to borrow because it is already mutably borrowed")]
@@ -129,6 +301,22 @@ pub enum RuntimeError {
     AssigningToDeallocatedReference,
     #[error("Cannot assign to an immutable reference")]
     CannotAssignToImmutableReference,
+    #[error("Cannot destructure '{0}' as a tuple")]
+    CannotDestructure(String),
+    #[error("Expected a tuple of {expected} elements but received {received}")]
+    DestructuringArityMismatch { expected: usize, received: usize },
+    #[error("Unable to write output: {0}")]
+    UnableToWriteOutput(String),
+    #[error("Cyclic reference")]
+    CyclicReference,
+    #[error("Cannot return reference to block-local value")]
+    CannotReturnReferenceToBlockLocalValue,
+}
+
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("{0} are not supported by the bytecode compiler yet")]
+    Unsupported(String),
 }
 
 pub type Result<T> = std::result::Result<T, MovaError>;
@@ -1,8 +1,17 @@
+pub use config::{ArithmeticMode, Config, DivisionMode, Input, Operators, Output};
 pub use data::Value;
-pub use evaluation::evaluate;
-pub use scope::Scope;
+pub use evaluation::{evaluate, evaluate_ref, evaluate_with};
+pub use native::{Arity, NativeFunction};
+pub use scope::{Scope, ScopeSnapshot};
 
+pub(crate) use builtin::native_builtin;
+pub(crate) use evaluation::{apply_unary, evaluate_binary_expression};
+
+mod builtin;
+mod config;
 mod data;
 mod evaluation;
+mod memo;
+mod native;
 mod reference;
 mod scope;
@@ -0,0 +1,348 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    error::{MovaError, Result, RuntimeError},
+    interpreter::{
+        config::Config,
+        data::Value,
+        evaluation::{evaluate_arguments, evaluate_expression},
+        memo::Memoized,
+        native::{Arity, NativeFunction},
+        scope::Scope,
+    },
+    parser::expression::{Expression, NamedArgument},
+};
+
+/// Evaluates a call to a builtin, or returns `None` if no builtin has that name
+pub fn evaluate_builtin(
+    scope: Rc<RefCell<Scope>>,
+    name: &str,
+    arguments: &[Expression],
+    named: &[NamedArgument],
+    config: &Config,
+) -> Option<Result<Option<Value>>> {
+    let evaluate = match name {
+        "drop" => evaluate_drop,
+        "clone" => evaluate_clone,
+        "print" => evaluate_print,
+        "range" => evaluate_range,
+        "input" => evaluate_input,
+        _ => return None,
+    };
+    if !named.is_empty() {
+        return Some(Err(MovaError::Runtime(
+            RuntimeError::NamedArgumentsNotSupported(name.to_string()),
+        )));
+    }
+    Some(evaluate(scope, arguments, config))
+}
+
+/// Builtins that only need evaluated arguments, or `None` if no such builtin has that name
+pub fn native_builtin(name: &str) -> Option<NativeFunction> {
+    match name {
+        "max" => Some(NativeFunction::new("max", Arity::Variadic { min: 1 }, max)),
+        "compare" => Some(NativeFunction::new("compare", Arity::Fixed(2), compare)),
+        "push" => Some(NativeFunction::new("push", Arity::Fixed(2), push)),
+        "pop" => Some(NativeFunction::new("pop", Arity::Fixed(1), pop)),
+        "set" => Some(NativeFunction::new("set", Arity::Fixed(3), set)),
+        "memoize" => Some(NativeFunction::new("memoize", Arity::Fixed(1), memoize)),
+        "to_number" => Some(NativeFunction::new("to_number", Arity::Fixed(1), to_number)),
+        "to_bool" => Some(NativeFunction::new("to_bool", Arity::Fixed(1), to_bool)),
+        "to_string" => Some(NativeFunction::new("to_string", Arity::Fixed(1), to_string)),
+        _ => None,
+    }
+}
+
+/// The largest of one or more numbers
+fn max(arguments: &[Value]) -> Result<Value> {
+    let mut largest = i32::MIN;
+    for argument in arguments {
+        match argument {
+            Value::Number(n) => largest = largest.max(*n),
+            value => {
+                return Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+                    "expected a number but received '{value}'"
+                ))));
+            }
+        }
+    }
+    Ok(Value::Number(largest))
+}
+
+/// Orders two numbers, returning -1, 0 or 1 when the first is less than, equal to or greater
+/// than the second
+fn compare(arguments: &[Value]) -> Result<Value> {
+    match arguments {
+        [Value::Number(left), Value::Number(right)] => Ok(Value::Number(left.cmp(right) as i32)),
+        [left, right] => Err(MovaError::Runtime(RuntimeError::CannotCompare {
+            left: left.to_string(),
+            right: right.to_string(),
+        })),
+        _ => Err(MovaError::Runtime(RuntimeError::InvalidArgumentCount {
+            expected: 2,
+            received: arguments.len(),
+        })),
+    }
+}
+
+/// Appends an element to the array behind a mutable reference, returning the new length
+fn push(arguments: &[Value]) -> Result<Value> {
+    update_array(&arguments[0], |elements| {
+        let length = i32::try_from(elements.len() + 1)
+            .map_err(|_| MovaError::Runtime(RuntimeError::ArrayTooLong))?;
+        elements.push(arguments[1].clone());
+        Ok(Value::Number(length))
+    })
+}
+
+/// Removes and returns the last element of the array behind a mutable reference
+fn pop(arguments: &[Value]) -> Result<Value> {
+    update_array(&arguments[0], |elements| {
+        elements
+            .pop()
+            .ok_or(MovaError::Runtime(RuntimeError::PopFromEmptyArray))
+    })
+}
+
+/// Replaces an element of the array behind a mutable reference, returning the previous one
+fn set(arguments: &[Value]) -> Result<Value> {
+    let Value::Number(index) = arguments[1] else {
+        return Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+            "expected a number as index but received '{}'",
+            arguments[1]
+        ))));
+    };
+    update_array(&arguments[0], |elements| {
+        let length = elements.len();
+        let element = usize::try_from(index)
+            .ok()
+            .and_then(|i| elements.get_mut(i))
+            .ok_or(MovaError::Runtime(RuntimeError::IndexOutOfBounds {
+                index,
+                length,
+            }))?;
+        Ok(std::mem::replace(element, arguments[2].clone()))
+    })
+}
+
+/// Wraps a function so that calls with numbers and booleans as arguments reuse earlier results
+fn memoize(arguments: &[Value]) -> Result<Value> {
+    match &arguments[0] {
+        function @ (Value::Function { .. } | Value::NativeFunction(_)) => {
+            Ok(Value::Memoized(Rc::new(Memoized::new(function.clone()))))
+        }
+        value => Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+            "expected a function but received '{value}'"
+        )))),
+    }
+}
+
+/// Parses text as a number, leaving numbers unchanged
+fn to_number(arguments: &[Value]) -> Result<Value> {
+    match &arguments[0] {
+        Value::Number(n) => Ok(Value::Number(*n)),
+        Value::String(text) => text.trim().parse().map(Value::Number).map_err(|_| {
+            MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+                "cannot convert '{text}' to a number"
+            )))
+        }),
+        value => Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+            "cannot convert {} '{value}' to a number",
+            value.type_name()
+        )))),
+    }
+}
+
+/// Treats any number but 0 as true, and parses the text `true` or `false`
+fn to_bool(arguments: &[Value]) -> Result<Value> {
+    match &arguments[0] {
+        Value::Boolean(b) => Ok(Value::Boolean(*b)),
+        Value::Number(n) => Ok(Value::Boolean(*n != 0)),
+        Value::String(text) => match text.trim() {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+                "cannot convert '{text}' to a boolean"
+            )))),
+        },
+        value => Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+            "cannot convert {} '{value}' to a boolean",
+            value.type_name()
+        )))),
+    }
+}
+
+/// Renders any value as text, the way `print` shows it
+fn to_string(arguments: &[Value]) -> Result<Value> {
+    Ok(Value::String(arguments[0].to_string().into()))
+}
+
+/// Arrays are values that move like any other, so they are only changed through a mutable
+/// borrow. The elements are changed in place, and since the array cannot be moved or read
+/// while borrowed mutably, no other binding observes the change until the borrow ends.
+fn update_array(
+    target: &Value,
+    update: impl FnOnce(&mut Vec<Value>) -> Result<Value>,
+) -> Result<Value> {
+    let Value::Reference(reference) = target else {
+        return Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+            "expected a mutable reference to an array but received '{target}'"
+        ))));
+    };
+    let data = reference.write()?;
+    let Value::Array(elements) = &data.value else {
+        return Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+            "expected a mutable reference to an array but received a reference to '{}'",
+            data.value
+        ))));
+    };
+
+    update(&mut elements.borrow_mut())
+}
+
+/// Ends the lifetime of a binding early, releasing any borrows it holds
+fn evaluate_drop(
+    scope: Rc<RefCell<Scope>>,
+    arguments: &[Expression],
+    config: &Config,
+) -> Result<Option<Value>> {
+    let [argument] = arguments else {
+        return Err(MovaError::Runtime(RuntimeError::InvalidArgumentCount {
+            expected: 1,
+            received: arguments.len(),
+        }));
+    };
+
+    match argument {
+        Expression::Identifier(name) => {
+            scope.borrow_mut().take(name)?;
+        }
+        // Temporaries are discarded as soon as they are evaluated
+        _ => {
+            evaluate_expression(argument, scope, config)?;
+        }
+    }
+
+    Ok(None)
+}
+
+/// Copies a value without moving it, so the original binding stays usable
+fn evaluate_clone(
+    scope: Rc<RefCell<Scope>>,
+    arguments: &[Expression],
+    config: &Config,
+) -> Result<Option<Value>> {
+    let [argument] = arguments else {
+        return Err(MovaError::Runtime(RuntimeError::InvalidArgumentCount {
+            expected: 1,
+            received: arguments.len(),
+        }));
+    };
+
+    let value = match argument {
+        Expression::Identifier(name) => scope.borrow().read(name)?,
+        _ => evaluate_expression(argument, scope, config)?.ok_or(MovaError::Runtime(
+            RuntimeError::ExpectedExpressionAsArgument,
+        ))?,
+    };
+
+    deep_clone(&value).map(Some)
+}
+
+/// Cloning a reference yields a copy of the value behind it rather than another borrow
+fn deep_clone(value: &Value) -> Result<Value> {
+    match value {
+        Value::Reference(reference) => {
+            let data = reference.read()?;
+            match &data.value {
+                Value::Moved => Err(MovaError::Runtime(RuntimeError::CannotReadFromMovedValue)),
+                value => deep_clone(value),
+            }
+        }
+        value => Ok(value.detach()),
+    }
+}
+
+/// Writes the arguments separated by spaces, followed by a newline
+fn evaluate_print(
+    scope: Rc<RefCell<Scope>>,
+    arguments: &[Expression],
+    config: &Config,
+) -> Result<Option<Value>> {
+    let values = evaluate_arguments(&scope, arguments, config)?;
+    let line: Vec<String> = values.iter().map(Value::to_string).collect();
+
+    writeln!(config.output.0.borrow_mut(), "{}", line.join(" "))
+        .map_err(|e| MovaError::Runtime(RuntimeError::UnableToWriteOutput(e.to_string())))?;
+    Ok(None)
+}
+
+/// Reads one line without its line ending, failing once the input is exhausted
+fn evaluate_input(
+    _scope: Rc<RefCell<Scope>>,
+    arguments: &[Expression],
+    config: &Config,
+) -> Result<Option<Value>> {
+    if !arguments.is_empty() {
+        return Err(MovaError::Runtime(RuntimeError::InvalidArgumentCount {
+            expected: 0,
+            received: arguments.len(),
+        }));
+    }
+
+    let mut line = String::new();
+    let read = config
+        .input
+        .0
+        .borrow_mut()
+        .read_line(&mut line)
+        .map_err(|e| MovaError::Runtime(RuntimeError::UnableToReadInput(e.to_string())))?;
+    if read == 0 {
+        return Err(MovaError::Runtime(RuntimeError::EndOfInput));
+    }
+
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    Ok(Some(Value::String(line.into())))
+}
+
+/// Builds an array of the numbers from `start` up to but excluding `end`
+fn evaluate_range(
+    scope: Rc<RefCell<Scope>>,
+    arguments: &[Expression],
+    config: &Config,
+) -> Result<Option<Value>> {
+    if arguments.len() != 2 {
+        return Err(MovaError::Runtime(RuntimeError::InvalidArgumentCount {
+            expected: 2,
+            received: arguments.len(),
+        }));
+    }
+
+    let values = evaluate_arguments(&scope, arguments, config)?;
+    let (start, end) = match (&values[0], &values[1]) {
+        (Value::Number(start), Value::Number(end)) => (*start, *end),
+        (start, end) => {
+            return Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+                "range expects two numbers but received '{start}' and '{end}'"
+            ))));
+        }
+    };
+    if start > end {
+        return Err(MovaError::Runtime(RuntimeError::InvalidArgument(format!(
+            "range start {start} is greater than end {end}"
+        ))));
+    }
+
+    let length = end.abs_diff(start) as usize;
+    if length > config.max_range_length {
+        return Err(MovaError::Runtime(RuntimeError::RangeTooLong {
+            length,
+            limit: config.max_range_length,
+        }));
+    }
+
+    Ok(Some(Value::Array(Rc::new(RefCell::new(
+        (start..end).map(Value::Number).collect(),
+    )))))
+}
@@ -0,0 +1,169 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::{
+    error::{MovaError, Result, RuntimeError},
+    interpreter::native::NativeFunction,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DivisionMode {
+    /// Rounds the quotient towards zero, so `-7 / 2` is `-3`
+    #[default]
+    Truncate,
+    /// Rounds the quotient towards negative infinity, so `-7 / 2` is `-4`
+    Floor,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ArithmeticMode {
+    /// Reports overflow as a runtime error
+    #[default]
+    Checked,
+    /// Clamps results to `i32::MIN` and `i32::MAX`
+    Saturating,
+    /// Wraps around on overflow using two's complement
+    Wrapping,
+}
+
+/// Where `print` writes, shared so that an embedder can read back what was written
+#[derive(Clone)]
+pub struct Output(pub Rc<RefCell<dyn Write>>);
+
+impl Default for Output {
+    fn default() -> Self {
+        Self(Rc::new(RefCell::new(io::stdout())))
+    }
+}
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Output")
+    }
+}
+
+/// Where `input` reads lines from, shared so that an embedder can script them
+#[derive(Clone)]
+pub struct Input(pub Rc<RefCell<dyn BufRead>>);
+
+impl Default for Input {
+    fn default() -> Self {
+        Self(Rc::new(RefCell::new(BufReader::new(io::stdin()))))
+    }
+}
+
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Input")
+    }
+}
+
+/// A binary operator implemented by the embedder for one pair of operand types
+#[derive(Debug, Clone)]
+struct Overload {
+    operator: String,
+    left: &'static str,
+    right: &'static str,
+    function: Rc<NativeFunction>,
+}
+
+/// Binary operators defined by the embedder, consulted before the built-in ones
+#[derive(Debug, Clone, Default)]
+pub struct Operators {
+    overloads: Vec<Overload>,
+}
+
+impl Operators {
+    /// Makes `operator` call `function` with both operands when their types are named
+    /// `left` and `right`, as in `Value::type_name`, replacing any earlier registration
+    pub fn register(
+        &mut self,
+        operator: &str,
+        left: &'static str,
+        right: &'static str,
+        function: NativeFunction,
+    ) {
+        self.overloads
+            .retain(|o| !(o.operator == operator && o.left == left && o.right == right));
+        self.overloads.push(Overload {
+            operator: operator.to_string(),
+            left,
+            right,
+            function: Rc::new(function),
+        });
+    }
+
+    /// The function registered for `operator` on operands of the given types
+    pub fn find(&self, operator: &str, left: &str, right: &str) -> Option<&NativeFunction> {
+        self.overloads
+            .iter()
+            .find(|o| o.operator == operator && o.left == left && o.right == right)
+            .map(|o| o.function.as_ref())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub division: DivisionMode,
+    pub arithmetic: ArithmeticMode,
+    /// Loop iterations left across all loops before evaluation is aborted, unlimited if `None`
+    pub iteration_budget: Option<Cell<usize>>,
+    pub output: Output,
+    pub input: Input,
+    /// Most elements `range` may produce, so a huge range fails instead of exhausting memory
+    pub max_range_length: usize,
+    /// Set from another thread, e.g. a `Ctrl-C` handler, to abort evaluation at the next
+    /// loop iteration or function call
+    pub interrupt: Arc<AtomicBool>,
+    pub operators: Operators,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            division: DivisionMode::default(),
+            arithmetic: ArithmeticMode::default(),
+            iteration_budget: None,
+            output: Output::default(),
+            input: Input::default(),
+            max_range_length: 1_000_000,
+            interrupt: Arc::default(),
+            operators: Operators::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Spends one loop iteration from the budget
+    pub(crate) fn consume_iteration(&self) -> Result<()> {
+        if let Some(budget) = &self.iteration_budget {
+            let remaining = budget.get();
+            if remaining == 0 {
+                return Err(MovaError::Runtime(RuntimeError::IterationLimitExceeded));
+            }
+            budget.set(remaining - 1);
+        }
+        Ok(())
+    }
+
+    /// Clears an interrupt left over from an earlier run, so that a reused config can run again
+    pub(crate) fn start_run(&self) {
+        self.interrupt.store(false, Ordering::Relaxed);
+    }
+
+    /// Fails once the interrupt flag has been set
+    pub(crate) fn check_interrupt(&self) -> Result<()> {
+        if self.interrupt.load(Ordering::Relaxed) {
+            return Err(MovaError::Runtime(RuntimeError::Interrupted));
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Ref, RefCell},
+    fmt,
+    rc::Rc,
+};
 
 use crate::{
-    interpreter::{reference::Reference, scope::Scope},
-    parser::expression::Expression,
+    error::{MovaError, Result, RuntimeError},
+    interpreter::{memo::Memoized, native::NativeFunction, reference::Reference, scope::Scope},
+    parser::{expression::Expression, statement::Parameter},
 };
 
 #[derive(Clone, Debug)]
@@ -10,41 +15,213 @@ pub enum Value {
     Number(i32),
     Boolean(bool),
     Function {
-        parameters: Rc<[String]>,
+        parameters: Rc<[Parameter]>,
         body: Rc<Expression>,
         definition_scope: Rc<RefCell<Scope>>,
     },
+    NativeFunction(Rc<NativeFunction>),
+    /// A function wrapped by `memoize`
+    Memoized(Rc<Memoized>),
     Reference(Rc<Reference>),
+    Tuple(Rc<[Value]>),
+    /// Elements are stored in place, so that `push`, `pop` and `set` don't copy the array
+    Array(Rc<RefCell<Vec<Value>>>),
+    /// Text read by `input`, since the language has no string literals yet
+    String(Rc<str>),
+    /// Declared with `let x` but not assigned yet
+    Uninitialized,
     Moved,
 }
 
-impl PartialEq for Value {
-    fn eq(&self, other: &Self) -> bool {
+impl Value {
+    /// Name of the value's type as shown in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::Function { .. } => "function",
+            Value::NativeFunction(_) => "native function",
+            Value::Memoized(_) => "memoized function",
+            Value::Reference(_) => "reference",
+            Value::Tuple(_) => "tuple",
+            Value::Array(_) => "array",
+            Value::String(_) => "string",
+            Value::Uninitialized => "uninitialized value",
+            Value::Moved => "moved value",
+        }
+    }
+
+    /// Whether using the value copies it instead of moving it out of its binding
+    pub fn is_copy(&self) -> bool {
+        match self {
+            Value::Number(_) | Value::Boolean(_) => true,
+            Value::Function { .. }
+            | Value::NativeFunction(_)
+            | Value::Memoized(_)
+            | Value::Reference(_)
+            | Value::Tuple(_)
+            | Value::Array(_)
+            | Value::String(_)
+            | Value::Uninitialized
+            | Value::Moved => false,
+        }
+    }
+
+    /// The number held by the value, if it is one
+    pub fn as_number(&self) -> Option<i32> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// The boolean held by the value, if it is one
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// The text held by the value, if it is a string
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The elements of the value, if it is an array
+    pub fn as_array(&self) -> Option<Ref<'_, [Value]>> {
+        match self {
+            Value::Array(elements) => Some(Ref::map(elements.borrow(), Vec::as_slice)),
+            _ => None,
+        }
+    }
+
+    /// A copy that shares no array storage with this value, so changing one leaves the
+    /// other as it was
+    pub fn detach(&self) -> Value {
+        match self {
+            Value::Array(elements) => Value::Array(Rc::new(RefCell::new(
+                elements.borrow().iter().map(Value::detach).collect(),
+            ))),
+            Value::Tuple(elements) => Value::Tuple(elements.iter().map(Value::detach).collect()),
+            value => value.clone(),
+        }
+    }
+
+    /// Whether both values are the very same function, i.e. share body and captured scope
+    pub fn same_function(&self, other: &Self) -> bool {
         match (self, other) {
-            (Value::Number(l), Value::Number(r)) => l == r,
-            (Value::Boolean(l), Value::Boolean(r)) => l == r,
-            (Value::Reference(l), Value::Reference(r)) => l == r,
-            (Value::Moved, Value::Moved) => true,
             (
                 Value::Function {
-                    parameters: lp,
                     body: lb,
                     definition_scope: ls,
+                    ..
                 },
                 Value::Function {
-                    parameters: rp,
                     body: rb,
                     definition_scope: rs,
+                    ..
                 },
-            ) => {
-                // For functions, we'll consider them equal only if they are the same instance
-                Rc::ptr_eq(lp, rp) && Rc::ptr_eq(lb, rb) && Rc::ptr_eq(ls, rs)
+            ) => Rc::ptr_eq(lb, rb) && Rc::ptr_eq(ls, rs),
+            _ => false,
+        }
+    }
+
+    /// Equality as seen by `==`, comparing functions and references by identity
+    pub fn equals(&self, other: &Self) -> Result<bool> {
+        match (self, other) {
+            (Value::Number(l), Value::Number(r)) => Ok(l == r),
+            (Value::Boolean(l), Value::Boolean(r)) => Ok(l == r),
+            (Value::String(l), Value::String(r)) => Ok(l == r),
+            (Value::Function { .. }, Value::Function { .. }) => Ok(self.same_function(other)),
+            (Value::NativeFunction(l), Value::NativeFunction(r)) => Ok(Rc::ptr_eq(l, r)),
+            (Value::Memoized(l), Value::Memoized(r)) => Ok(Rc::ptr_eq(l, r)),
+            (Value::Reference(l), Value::Reference(r)) => Ok(Rc::ptr_eq(&l.slot, &r.slot)),
+            (Value::Tuple(l), Value::Tuple(r)) if l.len() == r.len() => all_equal(l, r),
+            (Value::Array(l), Value::Array(r)) if l.borrow().len() == r.borrow().len() => {
+                all_equal(&l.borrow(), &r.borrow())
             }
+            (l, r) => Err(MovaError::Runtime(RuntimeError::CannotCompare {
+                left: l.to_string(),
+                right: r.to_string(),
+            })),
+        }
+    }
+}
+
+/// Compares elements of equally long sequences pairwise with `Value::equals`
+fn all_equal(left: &[Value], right: &[Value]) -> Result<bool> {
+    for (l, r) in left.iter().zip(right) {
+        if !l.equals(r)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Reference(l), Value::Reference(r)) => l == r,
+            (Value::Tuple(l), Value::Tuple(r)) => l == r,
+            (Value::Array(l), Value::Array(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Uninitialized, Value::Uninitialized) => true,
+            (Value::Moved, Value::Moved) => true,
+            // For functions, we'll consider them equal only if they are the same instance
+            (Value::Function { .. }, Value::Function { .. }) => self.same_function(other),
+            (Value::NativeFunction(l), Value::NativeFunction(r)) => Rc::ptr_eq(l, r),
+            (Value::Memoized(l), Value::Memoized(r)) => Rc::ptr_eq(l, r),
             _ => false,
         }
     }
 }
 
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Boolean(b) => write!(f, "{b}"),
+            Value::Function { parameters, .. } => {
+                let names: Vec<&str> = parameters.iter().map(|p| p.name.as_str()).collect();
+                write!(f, "fn({})", names.join(", "))
+            }
+            Value::NativeFunction(native) => write!(f, "<native fn {}>", native.name),
+            Value::Memoized(memoized) => write!(f, "<memoized {}>", memoized.function),
+            Value::Reference(r) => {
+                let prefix = if r.is_mutable { "&mut " } else { "&" };
+                match r.slot.try_borrow() {
+                    Ok(data) if data.state != State::Deallocated => {
+                        write!(f, "{prefix}{}", data.value)
+                    }
+                    Ok(_) => write!(f, "{prefix}<deallocated>"),
+                    Err(_) => write!(f, "{prefix}<in use>"),
+                }
+            }
+            Value::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(Value::to_string).collect();
+                match elements.as_slice() {
+                    [element] => write!(f, "({element},)"),
+                    elements => write!(f, "({})", elements.join(", ")),
+                }
+            }
+            Value::Array(elements) => {
+                let elements: Vec<String> =
+                    elements.borrow().iter().map(Value::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::String(text) => write!(f, "{text}"),
+            Value::Uninitialized => write!(f, "<uninitialized>"),
+            Value::Moved => write!(f, "<moved>"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum State {
     Free,
@@ -61,3 +238,149 @@ pub struct Data {
 }
 
 pub type Slot = Rc<RefCell<Data>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::Result, interpreter::evaluate, lexer::tokenize, parser::parse};
+
+    fn define(input: &str) -> Result<Rc<RefCell<Scope>>> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        evaluate(Rc::new(parse(tokenize(input)?)?), Rc::clone(&scope))?;
+        Ok(scope)
+    }
+
+    #[test]
+    fn test_function_bound_to_two_names_is_same() -> Result<()> {
+        let scope = define("fn f(x) = x")?;
+        let f = scope.borrow_mut().resolve("f")?;
+        scope.borrow_mut().declare("g", f.clone(), false);
+        scope.borrow_mut().declare("h", f, false);
+
+        let g = scope.borrow_mut().resolve("g")?;
+        let h = scope.borrow_mut().resolve("h")?;
+        assert!(g.same_function(&h));
+        assert_eq!(g, h);
+        Ok(())
+    }
+
+    #[test]
+    fn test_separate_definitions_are_not_same() -> Result<()> {
+        let scope = define("fn f(x) = x; fn g(x) = x")?;
+        let f = scope.borrow_mut().resolve("f")?;
+        let g = scope.borrow_mut().resolve("g")?;
+        assert!(!f.same_function(&g));
+        assert!(!f.same_function(&Value::Number(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_numbers_and_booleans_are_copy() -> Result<()> {
+        assert!(Value::Number(1).is_copy());
+        assert!(Value::Boolean(true).is_copy());
+
+        let scope = define("fn f(x) = x; let x = 1; let t = (1, 2)")?;
+        assert!(!scope.borrow().read("f")?.is_copy());
+        assert!(!scope.borrow().read("t")?.is_copy());
+        let x = scope.borrow().find_slot("x")?;
+        assert!(!Value::Reference(Rc::new(Reference::new(x, false)?)).is_copy());
+        Ok(())
+    }
+
+    #[test]
+    fn test_accessors_return_matching_values() -> Result<()> {
+        assert_eq!(Value::Number(7).as_number(), Some(7));
+        assert_eq!(Value::Boolean(false).as_bool(), Some(false));
+        assert_eq!(Value::String(Rc::from("text")).as_string(), Some("text"));
+
+        let scope = define("let numbers = range(1, 3)")?;
+        let numbers = scope.borrow().read("numbers")?;
+        assert_eq!(
+            numbers.as_array().as_deref(),
+            Some([Value::Number(1), Value::Number(2)].as_slice())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_accessors_reject_other_values() {
+        let values = [
+            Value::Number(1),
+            Value::Boolean(true),
+            Value::String(Rc::from("1")),
+            Value::Array(Rc::new(RefCell::new(vec![Value::Number(1)]))),
+            Value::Tuple(Rc::from([Value::Number(1), Value::Boolean(true)])),
+            Value::Moved,
+        ];
+        for value in &values {
+            assert_eq!(
+                value.as_number().is_some(),
+                matches!(value, Value::Number(_))
+            );
+            assert_eq!(
+                value.as_bool().is_some(),
+                matches!(value, Value::Boolean(_))
+            );
+            assert_eq!(
+                value.as_string().is_some(),
+                matches!(value, Value::String(_))
+            );
+            assert_eq!(value.as_array().is_some(), matches!(value, Value::Array(_)));
+        }
+    }
+
+    #[test]
+    fn test_equals_compares_values() -> Result<()> {
+        assert!(Value::Number(1).equals(&Value::Number(1))?);
+        assert!(!Value::Number(1).equals(&Value::Number(2))?);
+        assert!(Value::Boolean(true).equals(&Value::Boolean(true))?);
+        assert!(!Value::Boolean(true).equals(&Value::Boolean(false))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_equals_compares_identity() -> Result<()> {
+        let scope = define("fn f(x) = x; fn g(x) = x; let x = 1; let y = 1")?;
+        let f = scope.borrow().read("f")?;
+        let g = scope.borrow().read("g")?;
+        assert!(f.equals(&f.clone())?);
+        assert!(!f.equals(&g)?);
+
+        let x = scope.borrow().find_slot("x")?;
+        let y = scope.borrow().find_slot("y")?;
+        let a = Value::Reference(Rc::new(Reference::new(Rc::clone(&x), false)?));
+        let b = Value::Reference(Rc::new(Reference::new(x, false)?));
+        let c = Value::Reference(Rc::new(Reference::new(y, false)?));
+        assert!(a.equals(&b)?);
+        assert!(!a.equals(&c)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_equals_rejects_incomparable_values() -> Result<()> {
+        let scope = define("fn f(x) = x; let x = 1")?;
+        let f = scope.borrow().read("f")?;
+        let x = scope.borrow().find_slot("x")?;
+        let reference = Value::Reference(Rc::new(Reference::new(x, false)?));
+
+        let pairs = [
+            (Value::Number(1), Value::Boolean(true)),
+            (Value::Number(1), f.clone()),
+            (Value::Boolean(false), reference.clone()),
+            (f, reference),
+            (Value::Moved, Value::Moved),
+            (Value::Uninitialized, Value::Number(1)),
+        ];
+        for (left, right) in pairs {
+            assert!(
+                left.equals(&right).is_err(),
+                "{left} == {right} should fail"
+            );
+            assert!(
+                right.equals(&left).is_err(),
+                "{right} == {left} should fail"
+            );
+        }
+        Ok(())
+    }
+}
@@ -3,31 +3,171 @@ use std::{cell::RefCell, rc::Rc};
 use crate::{
     error::{MovaError, Result, RuntimeError},
     interpreter::{
+        builtin::{evaluate_builtin, native_builtin},
+        config::{ArithmeticMode, Config, DivisionMode},
         data::{Data, Slot, State, Value},
+        memo::Memoized,
+        native::NativeFunction,
         reference::Reference,
         scope::Scope,
     },
-    parser::{expression::Expression, node::Node, statement::Statement},
+    parser::{
+        expression::{Arguments, Expression, NamedArgument},
+        node::Node,
+        statement::{Parameter, Pattern, Statement},
+    },
 };
 
-fn evaluate_binary_expression(operator: &str, left: Value, right: Value) -> Result<Value> {
+/// The quotient and matching remainder of a non-zero division, or `None` if the quotient of
+/// `i32::MIN / -1` overflows in checked mode
+fn divide(
+    left: i32,
+    right: i32,
+    mode: DivisionMode,
+    arithmetic: ArithmeticMode,
+) -> Option<(i32, i32)> {
+    // Only the quotient can overflow, the remainder of `i32::MIN / -1` is 0
+    let (quotient, remainder) = match arithmetic {
+        ArithmeticMode::Checked => (left.checked_div(right)?, left.checked_rem(right)?),
+        ArithmeticMode::Saturating => (left.saturating_div(right), left.wrapping_rem(right)),
+        ArithmeticMode::Wrapping => (left.wrapping_div(right), left.wrapping_rem(right)),
+    };
+    match mode {
+        DivisionMode::Floor if remainder != 0 && (remainder < 0) != (right < 0) => {
+            Some((quotient - 1, remainder + right))
+        }
+        DivisionMode::Truncate | DivisionMode::Floor => Some((quotient, remainder)),
+    }
+}
+
+fn overflow(operator: &str, left: i32, right: i32) -> MovaError {
+    MovaError::Runtime(RuntimeError::ArithmeticOverflow {
+        operator: operator.to_string(),
+        left,
+        right,
+    })
+}
+
+fn arithmetic(operator: &str, left: i32, right: i32, mode: ArithmeticMode) -> Result<Value> {
+    let result = match (mode, operator) {
+        (ArithmeticMode::Checked, "+") => left.checked_add(right),
+        (ArithmeticMode::Checked, "-") => left.checked_sub(right),
+        (ArithmeticMode::Checked, _) => left.checked_mul(right),
+        (ArithmeticMode::Saturating, "+") => Some(left.saturating_add(right)),
+        (ArithmeticMode::Saturating, "-") => Some(left.saturating_sub(right)),
+        (ArithmeticMode::Saturating, _) => Some(left.saturating_mul(right)),
+        (ArithmeticMode::Wrapping, "+") => Some(left.wrapping_add(right)),
+        (ArithmeticMode::Wrapping, "-") => Some(left.wrapping_sub(right)),
+        (ArithmeticMode::Wrapping, _) => Some(left.wrapping_mul(right)),
+    };
+    result
+        .map(Value::Number)
+        .ok_or_else(|| overflow(operator, left, right))
+}
+
+/// Reads the value behind a chain of references, so that `&x + 1` and `&&x + 1` work
+/// like `x + 1`
+fn auto_deref(value: Value) -> Result<Value> {
+    match value {
+        Value::Reference(r) => match r.resolve_value()? {
+            Value::Moved => Err(MovaError::Runtime(RuntimeError::CannotReadFromMovedValue)),
+            value => Ok(value),
+        },
+        value => Ok(value),
+    }
+}
+
+/// Binary operators the interpreter implements for at least some operand types
+const BINARY_OPERATORS: [&str; 15] = [
+    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "<", ">", "==", "!=", "&&",
+];
+
+pub(crate) fn evaluate_binary_expression(
+    operator: &str,
+    left: Value,
+    right: Value,
+    config: &Config,
+) -> Result<Value> {
+    // Equality compares references by identity, so only the other operators look through them
+    let (left, right) = match operator {
+        "+" | "-" | "*" | "/" | "%" | "<" | ">" | "&" | "|" | "^" | "<<" | ">>" => {
+            (auto_deref(left)?, auto_deref(right)?)
+        }
+        _ => (left, right),
+    };
+
+    if let Some(overload) = config
+        .operators
+        .find(operator, left.type_name(), right.type_name())
+    {
+        return overload.call(&[left, right]);
+    }
+
     match (operator, left, right) {
-        ("+", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
-        ("-", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l - r)),
-        ("*", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l * r)),
-        ("/", Value::Number(l), Value::Number(r)) => {
+        ("+" | "-" | "*", Value::Number(l), Value::Number(r)) => {
+            arithmetic(operator, l, r, config.arithmetic)
+        }
+        ("/" | "%", Value::Number(l), Value::Number(r)) => {
             if r == 0 {
                 return Err(MovaError::Runtime(RuntimeError::DivisionByZero));
             }
-            Ok(Value::Number(l / r))
+            // The remainder always satisfies `l == (l / r) * r + l % r` for the active mode
+            let (quotient, remainder) = divide(l, r, config.division, config.arithmetic)
+                .ok_or_else(|| overflow(operator, l, r))?;
+            let result = if operator == "/" { quotient } else { remainder };
+            Ok(Value::Number(result))
+        }
+        ("&", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l & r)),
+        ("|", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l | r)),
+        ("^", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l ^ r)),
+        ("<<" | ">>", Value::Number(l), Value::Number(r)) => {
+            let shifted = match (operator, u32::try_from(r)) {
+                ("<<", Ok(amount)) => l.checked_shl(amount),
+                (_, Ok(amount)) => l.checked_shr(amount),
+                (_, Err(_)) => None,
+            };
+            shifted
+                .map(Value::Number)
+                .ok_or(MovaError::Runtime(RuntimeError::ShiftOutOfRange(r)))
         }
         ("<", Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l < r)),
         (">", Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l > r)),
-        ("==", Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l == r)),
-        (o, l, r) => Err(MovaError::Runtime(RuntimeError::UnexpectedOperator {
+        ("==", l, r) => Ok(Value::Boolean(l.equals(&r)?)),
+        ("!=", l, r) => Ok(Value::Boolean(!l.equals(&r)?)),
+        ("&&", Value::Boolean(l), Value::Boolean(r)) => Ok(Value::Boolean(l && r)),
+        // A custom precedence table can make the parser accept operators that are not implemented
+        (o, _, _) if !BINARY_OPERATORS.contains(&o) => Err(MovaError::Runtime(
+            RuntimeError::UnknownOperator(o.to_string()),
+        )),
+        (o, l, r) => Err(MovaError::Runtime(RuntimeError::OperatorNotDefined {
             operator: o.to_string(),
-            left: format!("{l:?}"),
-            right: format!("{r:?}"),
+            left: l.type_name(),
+            right: r.type_name(),
+        })),
+    }
+}
+
+/// Evaluates the prefix `~` and `-` operators, which look through references like arithmetic
+fn evaluate_unary(
+    operator: &'static str,
+    operand: &Rc<Expression>,
+    scope: Rc<RefCell<Scope>>,
+    config: &Config,
+) -> Result<Value> {
+    let value = evaluate_expression(operand, scope, config)?
+        .ok_or(MovaError::Runtime(RuntimeError::ExpectedExpressionAsValue))?;
+    apply_unary(operator, value, config)
+}
+
+/// Applies a prefix operator to an already evaluated operand
+pub(crate) fn apply_unary(operator: &'static str, value: Value, config: &Config) -> Result<Value> {
+    match (operator, auto_deref(value)?) {
+        ("~", Value::Number(n)) => Ok(Value::Number(!n)),
+        // Negating `i32::MIN` overflows, which the arithmetic mode decides how to handle
+        ("-", Value::Number(n)) => arithmetic("-", 0, n, config.arithmetic),
+        (operator, value) => Err(MovaError::Runtime(RuntimeError::UnaryOperatorNotDefined {
+            operator,
+            operand: value.type_name(),
         })),
     }
 }
@@ -35,74 +175,344 @@ fn evaluate_binary_expression(operator: &str, left: Value, right: Value) -> Resu
 fn evaluate_call(
     scope: Rc<RefCell<Scope>>,
     name: &str,
-    arguments: Rc<[Expression]>,
+    arguments: Rc<Arguments>,
+    config: &Config,
 ) -> Result<Option<Value>> {
+    config.check_interrupt()?;
+    let Arguments { positional, named } = &*arguments;
     // Drop immediately after use so that recursive calls don't panic
-    let callee = { scope.borrow_mut().resolve(name)? };
+    // Calling a function only reads it, so it stays usable for later calls
+    let callee = { scope.borrow().read(name) };
+    let callee = match callee {
+        Err(MovaError::Runtime(RuntimeError::UnableToResolve(_))) => {
+            // Builtins are only consulted when no binding shadows them
+            if let Some(result) =
+                evaluate_builtin(Rc::clone(&scope), name, positional, named, config)
+            {
+                return result;
+            }
+            if let Some(native) = native_builtin(name) {
+                return call_native(&native, &scope, positional, named, config);
+            }
+            return Err(MovaError::Runtime(RuntimeError::CallToUndefinedFunction(
+                name.to_string(),
+            )));
+        }
+        callee => callee?,
+    };
     match callee {
         Value::Function {
             parameters,
             body,
             definition_scope,
         } => {
-            let argument_count = arguments.len();
-            let parameter_count = parameters.len();
-            if argument_count != parameter_count {
-                return Err(MovaError::Runtime(RuntimeError::InvalidArgumentCount {
-                    expected: parameter_count,
-                    received: argument_count,
-                }));
-            }
+            let evaluated_arguments =
+                match_arguments(&scope, name, &parameters, positional, named, config)?;
+            call_function(
+                name,
+                &parameters,
+                &body,
+                &definition_scope,
+                evaluated_arguments,
+                config,
+            )
+        }
+        Value::NativeFunction(native) => call_native(&native, &scope, positional, named, config),
+        Value::Memoized(memoized) => {
+            call_memoized(&memoized, &scope, name, positional, named, config)
+        }
+        value => Err(MovaError::Runtime(RuntimeError::NotCallable {
+            name: name.to_string(),
+            kind: value.type_name(),
+        })),
+    }
+}
 
-            let evaluated_arguments: Vec<Value> = arguments
-                .iter()
-                .map(|argument| {
-                    let node = Rc::new(Node::Expression(Rc::new(argument.clone())));
-                    let value = evaluate(node, Rc::clone(&scope))?.ok_or(MovaError::Runtime(
-                        RuntimeError::ExpectedExpressionAsArgument,
-                    ))?;
-                    Ok(value)
+/// Calls a function wrapped by `memoize`, reusing the result of an earlier call with the same
+/// arguments
+fn call_memoized(
+    memoized: &Memoized,
+    scope: &Rc<RefCell<Scope>>,
+    name: &str,
+    positional: &[Expression],
+    named: &[NamedArgument],
+    config: &Config,
+) -> Result<Option<Value>> {
+    if !named.is_empty() {
+        return Err(MovaError::Runtime(
+            RuntimeError::NamedArgumentsNotSupported(name.to_string()),
+        ));
+    }
+    let arguments = evaluate_arguments(scope, positional, config)?;
+    if let Some(result) = memoized.get(&arguments) {
+        return Ok(Some(result));
+    }
+
+    let result = match &memoized.function {
+        Value::Function {
+            parameters,
+            body,
+            definition_scope,
+        } => {
+            check_argument_count(parameters, arguments.len())?;
+            let arguments = arguments.iter().cloned().map(Some).collect();
+            call_function(name, parameters, body, definition_scope, arguments, config)?
+        }
+        Value::NativeFunction(native) => Some(native.call(&arguments)?),
+        value => {
+            return Err(MovaError::Runtime(RuntimeError::NotCallable {
+                name: name.to_string(),
+                kind: value.type_name(),
+            }));
+        }
+    };
+    if let Some(result) = &result {
+        memoized.insert(&arguments, result);
+    }
+    Ok(result)
+}
+
+/// Runs a function's body with its arguments bound in a fresh execution scope
+fn call_function(
+    name: &str,
+    parameters: &[Parameter],
+    body: &Expression,
+    definition_scope: &Rc<RefCell<Scope>>,
+    arguments: Vec<Option<Value>>,
+    config: &Config,
+) -> Result<Option<Value>> {
+    // Create execution scope in order to avoid interfering with other calls
+    let execution_scope = Rc::new(RefCell::new(Scope::new(Some(Rc::clone(definition_scope)))));
+    let result = bind_parameters(
+        &execution_scope,
+        definition_scope,
+        parameters,
+        arguments,
+        config,
+    )
+    .and_then(|()| evaluate_expression(body, Rc::clone(&execution_scope), config));
+    // A `return` anywhere in the body ends the call with its value
+    let result = match result {
+        Err(MovaError::Return(value)) => Ok(value),
+        result => result.map_err(|error| error.in_frame(name)),
+    };
+
+    Scope::close(&execution_scope);
+
+    result
+}
+
+fn check_argument_count(parameters: &[Parameter], received: usize) -> Result<()> {
+    let maximum = parameters.len();
+    let minimum = parameters
+        .iter()
+        .take_while(|p| p.default.is_none())
+        .count();
+
+    if minimum == maximum && received != maximum {
+        Err(MovaError::Runtime(RuntimeError::InvalidArgumentCount {
+            expected: maximum,
+            received,
+        }))
+    } else if received < minimum {
+        Err(MovaError::Runtime(RuntimeError::TooFewArguments {
+            minimum,
+            received,
+        }))
+    } else if received > maximum {
+        Err(MovaError::Runtime(RuntimeError::TooManyArguments {
+            maximum,
+            received,
+        }))
+    } else {
+        Ok(())
+    }
+}
+
+/// Evaluates positional and then named arguments, placing each at its parameter's position.
+/// Parameters that received no argument are left as `None`.
+fn match_arguments(
+    scope: &Rc<RefCell<Scope>>,
+    function: &str,
+    parameters: &[Parameter],
+    positional: &[Expression],
+    named: &[NamedArgument],
+    config: &Config,
+) -> Result<Vec<Option<Value>>> {
+    if named.is_empty() {
+        check_argument_count(parameters, positional.len())?;
+    } else if positional.len() > parameters.len() {
+        return Err(MovaError::Runtime(RuntimeError::TooManyArguments {
+            maximum: parameters.len(),
+            received: positional.len() + named.len(),
+        }));
+    }
+
+    let mut arguments: Vec<Option<Value>> = evaluate_arguments(scope, positional, config)?
+        .into_iter()
+        .map(Some)
+        .collect();
+    arguments.resize(parameters.len(), None);
+
+    for argument in named {
+        let index = parameters
+            .iter()
+            .position(|parameter| parameter.name == argument.name)
+            .ok_or_else(|| {
+                MovaError::Runtime(RuntimeError::UnknownArgument {
+                    function: function.to_string(),
+                    argument: argument.name.to_string(),
                 })
-                .collect::<Result<Vec<Value>>>()?;
+            })?;
+        if arguments[index].is_some() {
+            return Err(MovaError::Runtime(RuntimeError::DuplicateArgument(
+                argument.name.to_string(),
+            )));
+        }
 
-            // Create execution scope in order to avoid interfering with other calls
-            let execution_scope =
-                Rc::new(RefCell::new(Scope::new(Some(Rc::clone(&definition_scope)))));
-            {
-                let mut s = execution_scope.borrow_mut();
+        let value = evaluate_expression(&argument.value, Rc::clone(scope), config)?.ok_or(
+            MovaError::Runtime(RuntimeError::ExpectedExpressionAsArgument),
+        )?;
+        arguments[index] = Some(value);
+    }
+
+    Ok(arguments)
+}
 
-                // Map arguments to parameters
-                evaluated_arguments
-                    .into_iter()
-                    .zip(parameters.iter())
-                    .for_each(|(value, parameter)| s.declare(parameter, value, false));
+/// Declares each parameter in the execution scope, evaluating defaults
+/// in the definition scope for arguments that were left out
+fn bind_parameters(
+    execution_scope: &Rc<RefCell<Scope>>,
+    definition_scope: &Rc<RefCell<Scope>>,
+    parameters: &[Parameter],
+    arguments: Vec<Option<Value>>,
+    config: &Config,
+) -> Result<()> {
+    for (parameter, argument) in parameters.iter().zip(arguments) {
+        let value = match (argument, &parameter.default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => {
+                evaluate_expression(default, Rc::clone(definition_scope), config)?.ok_or(
+                    MovaError::Runtime(RuntimeError::ExpectedExpressionAsArgument),
+                )?
             }
+            (None, None) => {
+                return Err(MovaError::Runtime(RuntimeError::MissingArgument(
+                    parameter.name.to_string(),
+                )));
+            }
+        };
+        execution_scope
+            .borrow_mut()
+            .declare(&parameter.name, value, false);
+    }
+    Ok(())
+}
 
-            let result = evaluate(
-                Rc::new(Node::Expression(Rc::clone(&body))),
-                Rc::clone(&execution_scope),
-            );
+fn call_native(
+    native: &NativeFunction,
+    scope: &Rc<RefCell<Scope>>,
+    arguments: &[Expression],
+    named: &[NamedArgument],
+    config: &Config,
+) -> Result<Option<Value>> {
+    if !named.is_empty() {
+        return Err(MovaError::Runtime(
+            RuntimeError::NamedArgumentsNotSupported(native.name.clone()),
+        ));
+    }
+    let arguments = evaluate_arguments(scope, arguments, config)?;
+    native.call(&arguments).map(Some)
+}
 
-            execution_scope.borrow_mut().invalidate();
+/// Evaluates call arguments strictly from left to right, stopping at the first error,
+/// so moves and other side effects happen in source order
+pub fn evaluate_arguments(
+    scope: &Rc<RefCell<Scope>>,
+    arguments: &[Expression],
+    config: &Config,
+) -> Result<Vec<Value>> {
+    arguments
+        .iter()
+        .map(|argument| {
+            evaluate_expression(argument, Rc::clone(scope), config)?.ok_or(MovaError::Runtime(
+                RuntimeError::ExpectedExpressionAsArgument,
+            ))
+        })
+        .collect()
+}
 
-            result
+fn evaluate_operand(
+    operand: &Rc<Expression>,
+    scope: Rc<RefCell<Scope>>,
+    config: &Config,
+    is_borrowed: bool,
+) -> Result<Option<Value>> {
+    match &**operand {
+        Expression::Identifier(name) if is_borrowed => Ok(Some(scope.borrow().read(name)?)),
+        _ => evaluate_expression(operand, scope, config),
+    }
+}
+
+/// Reads one element of an array or tuple, copying it out so the target is left in place.
+/// The target of `a[0][1]` is itself the element read by `a[0]`.
+fn evaluate_index(
+    target: &Rc<Expression>,
+    index: &Rc<Expression>,
+    scope: Rc<RefCell<Scope>>,
+    config: &Config,
+) -> Result<Value> {
+    let target = evaluate_operand(target, Rc::clone(&scope), config, true)?
+        .ok_or(MovaError::Runtime(RuntimeError::ExpectedExpressionAsValue))?;
+    let target = match target {
+        Value::Reference(r) => r.read()?.value.clone(),
+        target => target,
+    };
+    let index = match evaluate_expression(index, scope, config)? {
+        Some(Value::Number(n)) => n,
+        Some(value) => {
+            return Err(MovaError::Runtime(RuntimeError::IndexMustBeNumber(
+                value.to_string(),
+            )));
         }
-        _ => Err(MovaError::Runtime(RuntimeError::NotCallable(name.to_string()))),
+        None => return Err(MovaError::Runtime(RuntimeError::ExpectedExpressionAsValue)),
+    };
+
+    let element = |elements: &[Value]| {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| elements.get(i))
+            .map(Value::detach)
+            .ok_or(MovaError::Runtime(RuntimeError::IndexOutOfBounds {
+                index,
+                length: elements.len(),
+            }))
+    };
+    match &target {
+        Value::Array(elements) => element(&elements.borrow()),
+        Value::Tuple(elements) => element(elements),
+        value => Err(MovaError::Runtime(RuntimeError::NotIndexable(
+            value.type_name(),
+        ))),
     }
 }
 
 fn evaluate_slot(expression: &Expression, scope: Rc<RefCell<Scope>>) -> Result<Slot> {
     match expression {
         Expression::Identifier(name) => scope.borrow().find_slot(name),
-        _ => Err(MovaError::Runtime(RuntimeError::ExpressionCannotBeReferenced)),
+        _ => Err(MovaError::Runtime(
+            RuntimeError::ExpressionCannotBeReferenced,
+        )),
     }
 }
 
-fn evaluate_expression(
-    expression: Rc<Expression>,
+/// Evaluates an expression directly, without wrapping it in a `Node` first
+pub(crate) fn evaluate_expression(
+    expression: &Expression,
     scope: Rc<RefCell<Scope>>,
+    config: &Config,
 ) -> Result<Option<Value>> {
-    match &*expression {
+    match expression {
         Expression::Number(n) => Ok(Some(Value::Number(*n))),
         Expression::Boolean(b) => Ok(Some(Value::Boolean(*b))),
         Expression::Identifier(i) => {
@@ -118,13 +528,9 @@ fn evaluate_expression(
             let slot = if is_lvalue {
                 evaluate_slot(target_data, Rc::clone(&scope))?
             } else {
-                let val = evaluate(
-                    Rc::new(Node::Expression(Rc::clone(target_data))),
-                    Rc::clone(&scope),
-                )?
-                .ok_or(MovaError::Runtime(
-                    RuntimeError::ReferenceTargetYieldedNoValue,
-                ))?;
+                let val = evaluate_expression(target_data, Rc::clone(&scope), config)?.ok_or(
+                    MovaError::Runtime(RuntimeError::ReferenceTargetYieldedNoValue),
+                )?;
 
                 Rc::new(RefCell::new(Data {
                     value: val,
@@ -141,33 +547,38 @@ fn evaluate_expression(
             left,
             right,
         } => {
-            let left = evaluate(
-                Rc::new(Node::Expression(Rc::clone(left))),
-                Rc::clone(&scope),
-            )?
-            .ok_or(MovaError::Runtime(
-                RuntimeError::ExpectedExpressionAsLeftOperand,
-            ))?;
-
-            let right = evaluate(
-                Rc::new(Node::Expression(Rc::clone(right))),
-                Rc::clone(&scope),
-            )?
-            .ok_or(MovaError::Runtime(
-                RuntimeError::ExpectedExpressionAsRightOperand,
-            ))?;
-
-            Ok(Some(evaluate_binary_expression(operator, left, right)?))
-        }
-        Expression::Call { name, arguments } => evaluate_call(scope, name, Rc::clone(arguments)),
+            // Comparing only inspects its operands, so named values are not moved
+            let is_comparison = matches!(operator.as_str(), "==" | "!=");
+
+            let left = evaluate_operand(left, Rc::clone(&scope), config, is_comparison)?.ok_or(
+                MovaError::Runtime(RuntimeError::ExpectedExpressionAsLeftOperand),
+            )?;
+
+            // `&&` skips its right operand once the left one is false
+            if operator.as_str() == "&&" && left == Value::Boolean(false) {
+                return Ok(Some(left));
+            }
+
+            let right = evaluate_operand(right, Rc::clone(&scope), config, is_comparison)?.ok_or(
+                MovaError::Runtime(RuntimeError::ExpectedExpressionAsRightOperand),
+            )?;
+
+            Ok(Some(evaluate_binary_expression(
+                operator, left, right, config,
+            )?))
+        }
+        Expression::Call { name, arguments } => {
+            evaluate_call(scope, name, Rc::clone(arguments), config)
+        }
+        Expression::Index { target, index } => {
+            evaluate_index(target, index, scope, config).map(Some)
+        }
+        Expression::BitwiseNot(inner) => evaluate_unary("~", inner, scope, config).map(Some),
+        Expression::Negate(inner) => evaluate_unary("-", inner, scope, config).map(Some),
         Expression::Dereference(inner) => {
-            let val = evaluate(
-                Rc::new(Node::Expression(Rc::clone(inner))),
-                Rc::clone(&scope),
-            )?
-            .ok_or(MovaError::Runtime(
-                RuntimeError::DereferenceTargetYieldedNoValue,
-            ))?;
+            let val = evaluate_expression(inner, Rc::clone(&scope), config)?.ok_or(
+                MovaError::Runtime(RuntimeError::DereferenceTargetYieldedNoValue),
+            )?;
 
             if let Value::Reference(r) = val {
                 let data = r.read()?;
@@ -181,39 +592,23 @@ fn evaluate_expression(
                 ))
             }
         }
-        Expression::Block(b) => {
-            let child_scope = Rc::new(RefCell::new(Scope::new(Some(Rc::clone(&scope)))));
-            let mut result = None;
-            for node in b.into_iter() {
-                result = evaluate(Rc::new(node.clone()), Rc::clone(&child_scope))?;
-            }
-
-            child_scope.borrow_mut().invalidate();
-
-            Ok(result)
+        Expression::Block(b) => evaluate_block(b, scope, config),
+        Expression::Do(inner) => {
+            evaluate_block(&[Node::Expression(Rc::clone(inner))], scope, config)
         }
         Expression::If {
             condition,
             consequence,
             alternative,
         } => {
-            let condition_value = evaluate(
-                Rc::new(Node::Expression(Rc::clone(condition))),
-                Rc::clone(&scope),
-            )?
-            .ok_or_else(|| MovaError::Runtime(RuntimeError::ConditionYieldedNoValue))?;
+            let condition_value = evaluate_expression(condition, Rc::clone(&scope), config)?
+                .ok_or(MovaError::Runtime(RuntimeError::ConditionYieldedNoValue))?;
 
             match condition_value {
-                Value::Boolean(true) => evaluate(
-                    Rc::new(Node::Expression(Rc::clone(consequence))),
-                    Rc::clone(&scope),
-                ),
+                Value::Boolean(true) => evaluate_expression(consequence, Rc::clone(&scope), config),
                 Value::Boolean(false) => {
                     if let Some(alt) = alternative {
-                        evaluate(
-                            Rc::new(Node::Expression(Rc::clone(alt))),
-                            Rc::clone(&scope),
-                        )
+                        evaluate_expression(alt, Rc::clone(&scope), config)
                     } else {
                         Ok(None)
                     }
@@ -224,18 +619,14 @@ fn evaluate_expression(
         Expression::While { condition, body } => {
             let mut result = None;
             loop {
-                let condition_value = evaluate(
-                    Rc::new(Node::Expression(Rc::clone(condition))),
-                    Rc::clone(&scope),
-                )?
-                .ok_or_else(|| MovaError::Runtime(RuntimeError::ConditionYieldedNoValue))?;
+                let condition_value = evaluate_expression(condition, Rc::clone(&scope), config)?
+                    .ok_or(MovaError::Runtime(RuntimeError::ConditionYieldedNoValue))?;
 
                 match condition_value {
                     Value::Boolean(true) => {
-                        result = evaluate(
-                            Rc::new(Node::Expression(Rc::clone(body))),
-                            Rc::clone(&scope),
-                        )?;
+                        config.check_interrupt()?;
+                        config.consume_iteration()?;
+                        result = evaluate_expression(body, Rc::clone(&scope), config)?;
                     }
                     Value::Boolean(false) => break,
                     _ => return Err(MovaError::Runtime(RuntimeError::ConditionMustBeBoolean)),
@@ -243,101 +634,250 @@ fn evaluate_expression(
             }
             Ok(result)
         }
+        Expression::Tuple(elements) => {
+            let values = elements
+                .iter()
+                .map(|element| {
+                    evaluate_expression(element, Rc::clone(&scope), config)?
+                        .ok_or(MovaError::Runtime(RuntimeError::ExpectedExpressionAsValue))
+                })
+                .collect::<Result<Vec<Value>>>()?;
+            Ok(Some(Value::Tuple(values.into())))
+        }
+        Expression::Return(value) => {
+            let value = match value {
+                Some(value) => evaluate_expression(value, scope, config)?,
+                None => None,
+            };
+            Err(MovaError::Return(value))
+        }
         Expression::Program(p) => {
             let mut result = None;
-            for node in p.into_iter() {
-                result = evaluate(Rc::new(node.clone()), Rc::clone(&scope))?;
+            for node in p.iter() {
+                result = evaluate_with(node, Rc::clone(&scope), config)?;
             }
             Ok(result)
         }
     }
 }
 
-fn evaluate_statement(statement: Rc<Statement>, scope: Rc<RefCell<Scope>>) -> Result<()> {
+/// Evaluates nodes in a fresh child scope that is closed afterwards
+fn evaluate_block(
+    nodes: &[Node],
+    scope: Rc<RefCell<Scope>>,
+    config: &Config,
+) -> Result<Option<Value>> {
+    // The child only holds its parent weakly, so `scope` is kept until the block ends
+    let child_scope = Rc::new(RefCell::new(Scope::new(Some(Rc::clone(&scope)))));
+    let mut result = None;
+    let mut is_returning = false;
+    for node in nodes {
+        match evaluate_with(node, Rc::clone(&child_scope), config) {
+            Ok(value) => result = value,
+            // Leaving the block through `return` still releases its locals
+            Err(MovaError::Return(value)) => {
+                result = value;
+                is_returning = true;
+                break;
+            }
+            // Functions that escaped the block before the error still need its scope
+            Err(error) => {
+                Scope::close(&child_scope);
+                return Err(error);
+            }
+        }
+    }
+
+    // A reference into the block's own locals would dangle once they are deallocated
+    let is_dangling = matches!(
+        &result,
+        Some(Value::Reference(r)) if child_scope.borrow().owns(&r.slot)
+    );
+
+    Scope::close(&child_scope);
+
+    if is_dangling {
+        return Err(MovaError::Runtime(
+            RuntimeError::CannotReturnReferenceToBlockLocalValue,
+        ));
+    }
+    if is_returning {
+        return Err(MovaError::Return(result));
+    }
+
+    Ok(result)
+}
+
+/// Binds every name of the pattern, or none of them if the value does not fit it
+fn bind_pattern(
+    scope: &mut Scope,
+    pattern: &Pattern,
+    value: Value,
+    is_mutable: bool,
+) -> Result<()> {
+    check_pattern(scope, pattern, &value)?;
+    declare_pattern(scope, pattern, value, is_mutable);
+    Ok(())
+}
+
+/// Fails if the value does not have the shape of the pattern, or a name cannot be redeclared
+fn check_pattern(scope: &Scope, pattern: &Pattern, value: &Value) -> Result<()> {
+    match pattern {
+        Pattern::Identifier(name) => scope.check_redeclare(name, value),
+        Pattern::Tuple(patterns) => {
+            let Value::Tuple(elements) = value else {
+                return Err(MovaError::Runtime(RuntimeError::CannotDestructure(
+                    value.to_string(),
+                )));
+            };
+            if elements.len() != patterns.len() {
+                return Err(MovaError::Runtime(
+                    RuntimeError::DestructuringArityMismatch {
+                        expected: patterns.len(),
+                        received: elements.len(),
+                    },
+                ));
+            }
+
+            patterns
+                .iter()
+                .zip(elements.iter())
+                .try_for_each(|(pattern, element)| check_pattern(scope, pattern, element))
+        }
+    }
+}
+
+/// Declares the names of a pattern that `check_pattern` accepted for the value
+fn declare_pattern(scope: &mut Scope, pattern: &Pattern, value: Value, is_mutable: bool) {
+    match (pattern, value) {
+        (Pattern::Identifier(name), value) => scope.declare(name, value, is_mutable),
+        (Pattern::Tuple(patterns), Value::Tuple(elements)) => {
+            for (pattern, element) in patterns.iter().zip(elements.iter()) {
+                declare_pattern(scope, pattern, element.clone(), is_mutable);
+            }
+        }
+        // Any other shape was rejected by `check_pattern`
+        (Pattern::Tuple(_), _) => {}
+    }
+}
+
+/// Stores a value in an existing variable, as long as nothing borrows it and it is mutable
+/// or still uninitialized
+fn assign(scope: &Rc<RefCell<Scope>>, name: &str, new_value: Value) -> Result<()> {
+    let slot = scope.borrow().find_slot(name)?;
+    if let Value::Reference(r) = &new_value
+        && r.reaches(&slot)
+    {
+        return Err(MovaError::Runtime(RuntimeError::CyclicReference));
+    }
+    let mut data = slot.borrow_mut();
+
+    match data.state {
+        State::Deallocated => {
+            return Err(MovaError::Runtime(
+                RuntimeError::CannotAssignToDeallocatedVariable(name.to_string()),
+            ));
+        }
+        State::Borrowed(count) if count > 0 => {
+            return Err(MovaError::Runtime(
+                RuntimeError::CannotAssignToBorrowedVariable(name.to_string()),
+            ));
+        }
+        State::MutablyBorrowed => {
+            return Err(MovaError::Runtime(
+                RuntimeError::CannotAssignToMutablyBorrowedVariable(name.to_string()),
+            ));
+        }
+        _ => {}
+    }
+
+    // Like in Rust, an immutable variable may still receive its first value late
+    if data.is_mutable || matches!(data.value, Value::Uninitialized) {
+        data.value = new_value;
+        Ok(())
+    } else {
+        Err(MovaError::Runtime(
+            RuntimeError::CannotAssignToImmutableVariable(name.to_string()),
+        ))
+    }
+}
+
+fn evaluate_statement(
+    statement: Rc<Statement>,
+    scope: Rc<RefCell<Scope>>,
+    config: &Config,
+) -> Result<()> {
     match &*statement {
         Statement::Variable {
             name,
             value,
             is_mutable,
         } => {
-            let value = evaluate(
-                Rc::new(Node::Expression(Rc::clone(value))),
-                Rc::clone(&scope),
-            )?
-            .ok_or(MovaError::Runtime(
-                RuntimeError::ExpectedExpressionAsValue,
-            ))?;
-            scope.borrow_mut().declare(name, value, *is_mutable);
+            let value = match value {
+                Some(value) => evaluate_expression(value, Rc::clone(&scope), config)?
+                    .ok_or(MovaError::Runtime(RuntimeError::ExpectedExpressionAsValue))?,
+                None => Value::Uninitialized,
+            };
+            scope.borrow_mut().redeclare(name, value, *is_mutable)?;
         }
-        Statement::Assignment { name, value } => {
-            let new_value = evaluate(
-                Rc::new(Node::Expression(Rc::clone(value))),
-                Rc::clone(&scope),
-            )?
-            .ok_or(MovaError::Runtime(
-                RuntimeError::ExpectedExpressionAsValue,
-            ))?;
-
-            let slot = scope.borrow().find_slot(name)?;
-            let mut data = slot.borrow_mut();
-
-            match data.state {
-                State::Deallocated => {
-                    return Err(MovaError::Runtime(
-                        RuntimeError::CannotAssignToDeallocatedVariable(name.to_string()),
-                    ));
-                }
-                State::Borrowed(count) if count > 0 => {
-                    return Err(MovaError::Runtime(
-                        RuntimeError::CannotAssignToBorrowedVariable(name.to_string()),
-                    ));
-                }
-                State::MutablyBorrowed => {
-                    return Err(MovaError::Runtime(
-                        RuntimeError::CannotAssignToMutablyBorrowedVariable(name.to_string()),
-                    ));
-                }
-                _ => {}
-            }
+        Statement::Destructure {
+            pattern,
+            value,
+            is_mutable,
+        } => {
+            let value = evaluate_expression(value, Rc::clone(&scope), config)?
+                .ok_or(MovaError::Runtime(RuntimeError::ExpectedExpressionAsValue))?;
 
-            if data.is_mutable {
-                data.value = new_value;
-            } else {
+            bind_pattern(&mut scope.borrow_mut(), pattern, value, *is_mutable)?;
+        }
+        Statement::Assignment { name, value } => {
+            let new_value = evaluate_expression(value, Rc::clone(&scope), config)?
+                .ok_or(MovaError::Runtime(RuntimeError::ExpectedExpressionAsValue))?;
+            assign(&scope, name, new_value)?;
+        }
+        Statement::ChainedAssignment { names, value } => {
+            let new_value = evaluate_expression(value, Rc::clone(&scope), config)?
+                .ok_or(MovaError::Runtime(RuntimeError::ExpectedExpressionAsValue))?;
+            // Every name receives its own copy, which only values that are copied can provide
+            if !new_value.is_copy() {
                 return Err(MovaError::Runtime(
-                    RuntimeError::CannotAssignToImmutableVariable(name.to_string()),
+                    RuntimeError::CannotAssignToSeveralVariables(new_value.to_string()),
                 ));
             }
+            for name in names.iter().rev() {
+                assign(&scope, name, new_value.clone())?;
+            }
         }
         Statement::Function {
             name,
             parameters,
             body,
+            ..
         } => {
+            let definition_scope = Rc::new(RefCell::new(Scope::new(Some(Rc::clone(&scope)))));
+            scope.borrow_mut().add_closure(&definition_scope);
             let function = Value::Function {
                 parameters: Rc::clone(parameters),
                 body: Rc::clone(body),
-                definition_scope: Rc::new(RefCell::new(Scope::new(Some(Rc::clone(&scope))))),
+                definition_scope,
             };
-            scope.borrow_mut().declare(name, function, false);
+            scope.borrow_mut().redeclare(name, function, false)?;
         }
         Statement::DereferenceAssignment { target, value } => {
-            let target_val = evaluate(
-                Rc::new(Node::Expression(Rc::clone(target))),
-                Rc::clone(&scope),
-            )?
-            .ok_or(MovaError::Runtime(
-                RuntimeError::DereferenceTargetYieldedNoValue,
-            ))?;
-
-            let new_value = evaluate(
-                Rc::new(Node::Expression(Rc::clone(value))),
-                Rc::clone(&scope),
-            )?
-            .ok_or(MovaError::Runtime(
-                RuntimeError::AssignmentValueYieldedNoValue,
-            ))?;
+            let target_val = evaluate_expression(target, Rc::clone(&scope), config)?.ok_or(
+                MovaError::Runtime(RuntimeError::DereferenceTargetYieldedNoValue),
+            )?;
+
+            let new_value = evaluate_expression(value, Rc::clone(&scope), config)?.ok_or(
+                MovaError::Runtime(RuntimeError::AssignmentValueYieldedNoValue),
+            )?;
 
             if let Value::Reference(r) = target_val {
+                if let Value::Reference(new_reference) = &new_value
+                    && new_reference.reaches(&r.slot)
+                {
+                    return Err(MovaError::Runtime(RuntimeError::CyclicReference));
+                }
                 let mut data = r.write()?;
                 data.value = new_value;
             } else {
@@ -351,10 +891,23 @@ fn evaluate_statement(statement: Rc<Statement>, scope: Rc<RefCell<Scope>>) -> Re
 }
 
 pub fn evaluate(node: Rc<Node>, scope: Rc<RefCell<Scope>>) -> Result<Option<Value>> {
-    match &*node {
-        Node::Expression(e) => evaluate_expression(Rc::clone(e), scope),
+    evaluate_ref(&node, scope)
+}
+
+/// Evaluates a borrowed tree, sparing callers that own their AST an extra `Rc`
+pub fn evaluate_ref(node: &Node, scope: Rc<RefCell<Scope>>) -> Result<Option<Value>> {
+    evaluate_with(node, scope, &Config::default())
+}
+
+pub fn evaluate_with(
+    node: &Node,
+    scope: Rc<RefCell<Scope>>,
+    config: &Config,
+) -> Result<Option<Value>> {
+    match node {
+        Node::Expression(e) => evaluate_expression(e, scope, config),
         Node::Statement(s) => {
-            evaluate_statement(Rc::clone(s), scope)?;
+            evaluate_statement(Rc::clone(s), scope, config)?;
             Ok(None)
         }
     }
@@ -363,7 +916,20 @@ pub fn evaluate(node: Rc<Node>, scope: Rc<RefCell<Scope>>) -> Result<Option<Valu
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::runner::run;
+    use crate::{
+        interpreter::{
+            config::Input,
+            native::{Arity, NativeFunction},
+        },
+        lexer::{LexerOptions, tokenize, tokenize_with},
+        parser::{ParserOptions, PrecedenceTable, parse, parse_with},
+        runner::{run, run_all, run_with},
+    };
+    use std::{
+        cell::Cell,
+        sync::{Arc, atomic::Ordering},
+        time::{Duration, Instant},
+    };
 
     #[test]
     fn test_cannot_assign_to_borrowed_variable() {
@@ -463,21 +1029,14 @@ mod tests {
     }
 
     #[test]
-    fn test_auto_dereference_in_binary_expression_is_no_longer_supported() {
+    fn test_auto_dereference_in_binary_expression() {
         let input = "
             let x = 10;
             let y = &x;
             y + 5
         ";
         let result = run(input);
-        match &result {
-            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
-            Err(e) => assert!(
-                e.to_string().contains("Unexpected operator '+'"),
-                "Error message was: {}",
-                e
-            ),
-        }
+        assert_eq!(result.unwrap(), Some(Value::Number(15)));
     }
 
     #[test]
@@ -557,4 +1116,1628 @@ mod tests {
         let result = run(input);
         assert_eq!(result.unwrap(), Some(Value::Number(5)));
     }
+
+    #[test]
+    fn test_call_to_undefined_function() {
+        let input = "
+            let x = 10;
+            foo(x)
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string().contains("Call to undefined function 'foo'"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_undefined_variable_is_not_reported_as_call() {
+        let result = run("foo");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string().contains("Unable to resolve foo"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_expression_statements_run_for_side_effects() {
+        let input = "
+            let mut count = 0
+            fn store(target, value) = {
+                *target = value
+            }
+            store(&mut count, 1)
+            1 + 2
+            if true { count = count + 10 }
+            { count = count * 2 }
+            count + 1
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(23)));
+    }
+
+    #[test]
+    fn test_expression_statements_separated_by_semicolons() {
+        let input = "
+            let mut count = 0;
+            fn store(target, value) = { *target = value; };
+            store(&mut count, 5); count = count + 1; 7;
+            count
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(6)));
+    }
+
+    #[test]
+    fn test_truncating_division_is_default() {
+        let input = "
+            let q = (0 - 7) / 2
+            let r = (0 - 7) % 2
+            q * 10 + r
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(-31)));
+    }
+
+    #[test]
+    fn test_floor_division_mode() {
+        let config = Config {
+            division: DivisionMode::Floor,
+            ..Config::default()
+        };
+        let quotient = run_with("(0 - 7) / 2", &config);
+        assert_eq!(quotient.unwrap(), Some(Value::Number(-4)));
+        let remainder = run_with("(0 - 7) % 2", &config);
+        assert_eq!(remainder.unwrap(), Some(Value::Number(1)));
+        let remainder = run_with("7 % (0 - 2)", &config);
+        assert_eq!(remainder.unwrap(), Some(Value::Number(-1)));
+        let exact = run_with("(0 - 8) / 2", &config);
+        assert_eq!(exact.unwrap(), Some(Value::Number(-4)));
+    }
+
+    #[test]
+    fn test_smallest_number_can_be_written_as_literal() {
+        assert_eq!(run("-2147483648").unwrap(), Some(Value::Number(i32::MIN)));
+        assert_eq!(
+            run("-2147483648 == -2147483647 - 1").unwrap(),
+            Some(Value::Boolean(true))
+        );
+    }
+
+    #[test]
+    fn test_dividing_minimum_by_minus_one_overflows() {
+        for division in [DivisionMode::Truncate, DivisionMode::Floor] {
+            let config = Config {
+                division,
+                ..Config::default()
+            };
+            for operator in ["/", "%"] {
+                let input = format!("(-2147483647 - 1) {operator} -1");
+                match run_with(&input, &config) {
+                    Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+                    Err(e) => assert_eq!(
+                        e.to_string(),
+                        format!(
+                            "Runtime error: Arithmetic overflow in '-2147483648 {operator} -1'"
+                        )
+                    ),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_calling_non_function_names_its_type() {
+        let cases = [
+            ("let x = 5 x()", "'x' is a number and is not callable"),
+            ("let x = true; x()", "'x' is a boolean and is not callable"),
+            ("let x = (1, 2); x(1)", "'x' is a tuple and is not callable"),
+            (
+                "let y = 1; let x = &y; x()",
+                "'x' is a reference and is not callable",
+            ),
+        ];
+        for (input, message) in cases {
+            match run(input) {
+                Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+                Err(e) => assert!(e.to_string().contains(message), "Error message was: {}", e),
+            }
+        }
+        assert!(
+            run("missing()")
+                .unwrap_err()
+                .to_string()
+                .contains("undefined function 'missing'")
+        );
+    }
+
+    #[test]
+    fn test_default_parameter_values() {
+        let greet = "fn greet(name, greeting = 10) = name + greeting; ";
+        assert_eq!(
+            run(&format!("{greet}greet(1)")).unwrap(),
+            Some(Value::Number(11))
+        );
+        assert_eq!(
+            run(&format!("{greet}greet(1, 2)")).unwrap(),
+            Some(Value::Number(3))
+        );
+        assert_eq!(
+            run(&format!("{greet}greet()")).unwrap_err().to_string(),
+            "Runtime error: Expected at least 1 arguments but received 0"
+        );
+        assert!(
+            run(&format!("{greet}greet(1, 2, 3)"))
+                .unwrap_err()
+                .to_string()
+                .contains("Expected at most 2 arguments but received 3")
+        );
+    }
+
+    #[test]
+    fn test_default_is_evaluated_in_definition_scope() {
+        let input = "
+            let base = 100
+            fn f(x, y = base + 1) = x + y
+            fn g(base) = f(base)
+            g(1) + f(2, 0)
+        ";
+        assert_eq!(run(input).unwrap(), Some(Value::Number(104)));
+    }
+
+    #[test]
+    fn test_named_arguments() {
+        let f = "fn f(a, b, c = 100) = a - b + c; ";
+        let cases = [
+            ("f(b = 1, a = 10)", 109),
+            ("f(c = 0, b = 1, a = 10)", 9),
+            ("f(1, c = 0, b = 5)", -4),
+            ("f(1, 2, c = 3)", 2),
+        ];
+        for (call, expected) in cases {
+            assert_eq!(
+                run(&format!("{f}{call}")).unwrap(),
+                Some(Value::Number(expected)),
+                "{call}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_named_arguments() {
+        let f = "fn f(a, b, c = 100) = a - b + c; ";
+        let cases = [
+            ("f(1, z = 2)", "'f' has no parameter named 'z'"),
+            ("f(1, a = 2)", "Argument 'a' is given more than once"),
+            ("f(b = 1, b = 2)", "Argument 'b' is given more than once"),
+            ("f(b = 1)", "Missing argument for parameter 'a'"),
+            (
+                "f(1, 2, 3, 4, c = 5)",
+                "Expected at most 3 arguments but received 5",
+            ),
+            ("print(x = 1)", "'print' does not accept named arguments"),
+            ("max(x = 1)", "'max' does not accept named arguments"),
+        ];
+        for (call, message) in cases {
+            match run(&format!("{f}{call}")) {
+                Ok(val) => panic!("{call} should have failed but succeeded with: {:?}", val),
+                Err(e) => assert!(e.to_string().contains(message), "Error message was: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_variable_shadows_function() {
+        let input = "
+            fn x() = 1
+            let x = 2
+            x()
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("'x' is a number and is not callable"),
+                "Error message was: {}",
+                e
+            ),
+        }
+        assert_eq!(
+            run("fn x() = 1; let x = 2; x").unwrap(),
+            Some(Value::Number(2))
+        );
+    }
+
+    #[test]
+    fn test_shadowing_keeps_captured_function() {
+        let input = "
+            fn x() = 1
+            let f = x
+            let x = 2
+            x + f()
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(3)));
+    }
+
+    #[test]
+    fn test_function_shadows_variable() {
+        let result = run("let x = 2; fn x() = 5; x()");
+        assert_eq!(result.unwrap(), Some(Value::Number(5)));
+    }
+
+    #[test]
+    fn test_function_identity_equality() {
+        let input = "
+            fn f() = 1
+            fn g() = 1
+            let same = f == f
+            let different = f == g
+            let copy = clone(f) == f
+            same && copy && f != g && different == false
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn test_reference_identity_equality() {
+        let input = "
+            let x = 1
+            let y = 1
+            let a = &x
+            let b = &x
+            let c = &y
+            a == b && a != c && b != &y
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn test_comparing_function_with_number() {
+        let result = run("fn f() = 1; f == 1");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string().contains("Cannot compare 'fn()' with '1'"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_uninitialized_variable() {
+        let result = run("let x; x + 1");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Use of possibly-uninitialized variable 'x'"),
+                "Error message was: {}",
+                e
+            ),
+        }
+
+        let input = "
+            let x
+            let flag = 1 < 2
+            if flag { x = 1 } else { x = 2 }
+            x + 1
+        ";
+        assert_eq!(run(input).unwrap(), Some(Value::Number(2)));
+    }
+
+    #[test]
+    fn test_immutable_variable_is_initialized_once() {
+        let result = run("let x; x = 1; x = 2");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Cannot assign to immutable variable 'x'"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_tuple_return_value() {
+        let input = "
+            fn divmod(a, b) = (a / b, a % b)
+            let (q, r) = divmod(7, 3)
+            q * 10 + r
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(21)));
+    }
+
+    #[test]
+    fn test_tuple_display() {
+        let result = run("(1, true, (2,))").unwrap().unwrap();
+        assert_eq!(result.to_string(), "(1, true, (2,))");
+        assert_eq!(run("(3)").unwrap(), Some(Value::Number(3)));
+    }
+
+    #[test]
+    fn test_nested_destructuring() {
+        let input = "
+            let (a, (b, c)) = (1, (2, 3))
+            a * 100 + b * 10 + c
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(123)));
+
+        let result = run("let (a, (b, c)) = (1, 2)");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string().contains("Cannot destructure '2' as a tuple"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_parenthesized_name_binds_whole_value() {
+        assert_eq!(run("let (a) = 5; a").unwrap(), Some(Value::Number(5)));
+        assert_eq!(
+            run("let (a) = (1, 2); a").unwrap().unwrap().to_string(),
+            "(1, 2)"
+        );
+        assert_eq!(run("let (a,) = (7,); a").unwrap(), Some(Value::Number(7)));
+    }
+
+    #[test]
+    fn test_failed_destructuring_binds_nothing() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let program = parse(tokenize("let (a, (b, c)) = (1, 2)")?)?;
+        assert!(evaluate(Rc::new(program), Rc::clone(&scope)).is_err());
+        assert!(scope.borrow().find_slot("a").is_err());
+
+        let program = parse(tokenize("let mut b = 1; let r = &b; let (a, b) = (2, 3)")?)?;
+        assert!(evaluate(Rc::new(program), Rc::clone(&scope)).is_err());
+        assert!(scope.borrow().find_slot("a").is_err());
+        assert_eq!(scope.borrow().read("b")?, Value::Number(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_destructuring_wrong_arity() {
+        let result = run("let (a, b) = (1, 2, 3)");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Expected a tuple of 2 elements but received 3"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_destructuring_non_tuple() {
+        let result = run("let (a, b) = 1");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string().contains("Cannot destructure '1' as a tuple"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_reference_is_dereferenced_in_arithmetic() {
+        let input = "
+            let x = 41
+            let a = &x + 1
+            let b = 1 + &x
+            let c = &x < 42
+            a + b + if c { 1 } else { 0 }
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(85)));
+    }
+
+    #[test]
+    fn test_references_on_both_sides_of_binary_expression() {
+        let input = "
+            let mut a = 2;
+            let mut b = 3;
+            let sum = &a + &b;
+            let less = &a < &b;
+            a = a + sum;
+            b = b * 2;
+            if less { a + b } else { 0 }
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(13)));
+    }
+
+    #[test]
+    fn test_adding_borrows_without_semicolons() -> Result<()> {
+        let result = run("let a = 2\nlet b = 3\n&a + &b")?;
+        assert_eq!(result, Some(Value::Number(5)));
+
+        // Both borrows are released, so `a` and `b` can still be used
+        let results = run_all("let a = 2\nlet b = 3\n&a + &b\na + b", &Config::default())?;
+        assert_eq!(
+            results,
+            [None, None, Some(Value::Number(5)), Some(Value::Number(5))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_to_reference_reads_root_value() {
+        let input = "
+            let x = 7;
+            let r = &x;
+            let rr = &r;
+            rr + 1
+        ";
+        assert_eq!(run(input).unwrap(), Some(Value::Number(8)));
+        assert_eq!(run("let x = 7; ~&&x").unwrap(), Some(Value::Number(-8)));
+        assert_eq!(
+            run("let x = 7; &&x < 8").unwrap(),
+            Some(Value::Boolean(true))
+        );
+    }
+
+    #[test]
+    fn test_resolving_cyclic_reference_fails() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        scope.borrow_mut().declare("x", Value::Number(1), true);
+        let slot = scope.borrow().find_slot("x")?;
+        let reference = Rc::new(Reference::new(Rc::clone(&slot), false)?);
+        slot.borrow_mut().value = Value::Reference(Rc::clone(&reference));
+
+        let result = reference.resolve_value();
+        // Break the cycle so the slot can be freed
+        slot.borrow_mut().value = Value::Number(1);
+        assert!(matches!(
+            result,
+            Err(MovaError::Runtime(RuntimeError::CyclicReference))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_prefix_still_borrows() {
+        let input = "
+            let mut x = 1
+            let r = &mut x;
+            *r = 5
+            x
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(5)));
+        let result = run("let x = 1; &x").unwrap().unwrap();
+        assert!(matches!(result, Value::Reference(_)));
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        assert_eq!(run("6 & 3").unwrap(), Some(Value::Number(2)));
+        assert_eq!(run("6 | 3").unwrap(), Some(Value::Number(7)));
+        assert_eq!(run("6 ^ 3").unwrap(), Some(Value::Number(5)));
+        assert_eq!(run("1 << 4").unwrap(), Some(Value::Number(16)));
+        assert_eq!(run("(0 - 32) >> 2").unwrap(), Some(Value::Number(-8)));
+        assert_eq!(run("~5").unwrap(), Some(Value::Number(-6)));
+        // Shifts bind looser than arithmetic and tighter than comparisons
+        assert_eq!(run("1 << 2 + 1 == 8").unwrap(), Some(Value::Boolean(true)));
+        assert_eq!(
+            run("let x = 12; let r = &x; r & 4").unwrap(),
+            Some(Value::Number(4))
+        );
+    }
+
+    #[test]
+    fn test_over_shift() {
+        for input in ["1 << 32", "1 >> (0 - 1)"] {
+            let result = run(input);
+            match &result {
+                Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+                Err(e) => assert!(
+                    e.to_string().contains("Shift amount out of range"),
+                    "Error message was: {}",
+                    e
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_clone_keeps_original_usable() {
+        let input = "
+            fn inc(n) = n + 1
+            fn apply(h, n) = h(n)
+            let f = inc
+            apply(clone(f), 1) + apply(f, 2)
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(5)));
+    }
+
+    #[test]
+    fn test_clone_reference_copies_referent() {
+        let input = "
+            let y = 5
+            let r = &y
+            let c = clone(r)
+            let s = r
+            c + *s
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(10)));
+    }
+
+    #[test]
+    fn test_checked_overflow_is_default() {
+        let result = run("2147483647 + 1");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Arithmetic overflow in '2147483647 + 1'"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_saturating_arithmetic_mode() {
+        let config = Config {
+            arithmetic: ArithmeticMode::Saturating,
+            ..Config::default()
+        };
+        let result = run_with("2147483647 + 1", &config);
+        assert_eq!(result.unwrap(), Some(Value::Number(i32::MAX)));
+        let result = run_with("(0 - 2147483647) - 2", &config);
+        assert_eq!(result.unwrap(), Some(Value::Number(i32::MIN)));
+    }
+
+    #[test]
+    fn test_wrapping_arithmetic_mode() {
+        let config = Config {
+            arithmetic: ArithmeticMode::Wrapping,
+            ..Config::default()
+        };
+        let result = run_with("2147483647 + 1", &config);
+        assert_eq!(result.unwrap(), Some(Value::Number(i32::MIN)));
+        let result = run_with("65536 * 65536", &config);
+        assert_eq!(result.unwrap(), Some(Value::Number(0)));
+    }
+
+    #[test]
+    fn test_arithmetic_mode_applies_to_division() {
+        let expected = [
+            (ArithmeticMode::Saturating, i32::MAX),
+            (ArithmeticMode::Wrapping, i32::MIN),
+        ];
+        for (arithmetic, quotient) in expected {
+            let config = Config {
+                arithmetic,
+                ..Config::default()
+            };
+            let result = run_with("(-2147483647 - 1) / -1", &config);
+            assert_eq!(result.unwrap(), Some(Value::Number(quotient)));
+            let result = run_with("(-2147483647 - 1) % -1", &config);
+            assert_eq!(result.unwrap(), Some(Value::Number(0)));
+        }
+    }
+
+    #[test]
+    fn test_iteration_budget_stops_infinite_loop() {
+        let config = Config {
+            iteration_budget: Some(Cell::new(1000)),
+            ..Config::default()
+        };
+        let result = run_with("while true {}", &config);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string().contains("Iteration limit exceeded"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_iteration_budget_is_shared_across_loops() {
+        let input = "
+            let mut i = 0
+            while i < 3 { i = i + 1 }
+            let mut j = 0
+            while j < 3 { j = j + 1 }
+            i + j
+        ";
+        let config = Config {
+            iteration_budget: Some(Cell::new(6)),
+            ..Config::default()
+        };
+        assert_eq!(run_with(input, &config).unwrap(), Some(Value::Number(6)));
+
+        let config = Config {
+            iteration_budget: Some(Cell::new(5)),
+            ..Config::default()
+        };
+        assert!(run_with(input, &config).is_err());
+    }
+
+    #[test]
+    fn test_modulo_by_zero() {
+        let result = run("7 % 0");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string().contains("Division by zero"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_borrowed_node() -> Result<()> {
+        let program = parse(tokenize("let x = 2; x * 21")?)?;
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        assert_eq!(evaluate_ref(&program, scope)?, Some(Value::Number(42)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_indented_blocks_replace_braces() -> Result<()> {
+        let options = LexerOptions {
+            significant_indentation: true,
+            ..LexerOptions::default()
+        };
+        let input = "
+let mut total = 0
+let mut i = 0
+while i < 4
+    if i % 2 == 0
+        total = total + i
+    else
+        total = total + 10
+    i = i + 1
+total
+";
+        let program = parse(tokenize_with(input, options)?)?;
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        assert_eq!(evaluate_ref(&program, scope)?, Some(Value::Number(22)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_parsed_program_repeatedly() -> Result<()> {
+        // Evaluation moves values out of scopes but never out of the tree, so it can be reused
+        let program = parse(tokenize(
+            "fn double(n) = n * 2; let pair = (x, double(x)); pair",
+        )?)?;
+
+        let mut results = Vec::new();
+        for x in [1, 5] {
+            let scope = Rc::new(RefCell::new(Scope::new(None)));
+            scope.borrow_mut().declare("x", Value::Number(x), false);
+            results.push(evaluate_ref(&program, scope)?.unwrap().to_string());
+        }
+        assert_eq!(results, ["(1, 2)", "(5, 10)"]);
+        Ok(())
+    }
+
+    fn sum(arguments: &[Value]) -> Result<Value> {
+        arguments
+            .iter()
+            .try_fold(Value::Number(0), |total, argument| {
+                match (total, argument) {
+                    (Value::Number(total), Value::Number(n)) => Ok(Value::Number(total + n)),
+                    (_, value) => Err(MovaError::Runtime(RuntimeError::InvalidArgument(
+                        value.to_string(),
+                    ))),
+                }
+            })
+    }
+
+    fn run_with_native(input: &str, arity: Arity) -> Result<Option<Value>> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let native = NativeFunction::new("sum", arity, sum);
+        scope
+            .borrow_mut()
+            .declare("sum", Value::NativeFunction(Rc::new(native)), false);
+        evaluate(Rc::new(parse(tokenize(input)?)?), scope)
+    }
+
+    #[test]
+    fn test_variadic_native_function() -> Result<()> {
+        let variadic = Arity::Variadic { min: 0 };
+        assert_eq!(run_with_native("sum()", variadic)?, Some(Value::Number(0)));
+        assert_eq!(
+            run_with_native("sum(1, 2, 3, 4)", variadic)?,
+            Some(Value::Number(10))
+        );
+        assert_eq!(
+            run_with_native("let x = 5; sum(x, x * 2)", variadic)?,
+            Some(Value::Number(15))
+        );
+
+        let result = run_with_native("sum(1)", Arity::Variadic { min: 2 });
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Runtime error: Expected at least 2 arguments but received 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_native_function_enforces_argument_count() -> Result<()> {
+        assert_eq!(
+            run_with_native("sum(1, 2)", Arity::Fixed(2))?,
+            Some(Value::Number(3))
+        );
+
+        let result = run_with_native("sum(1, 2, 3)", Arity::Fixed(2));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Runtime error: Expected 2 arguments but received 3"
+        );
+        Ok(())
+    }
+
+    thread_local! {
+        static COUNTER: Cell<i32> = const { Cell::new(0) };
+    }
+
+    fn next(_: &[Value]) -> Result<Value> {
+        Ok(Value::Number(COUNTER.with(|counter| {
+            counter.set(counter.get() + 1);
+            counter.get()
+        })))
+    }
+
+    fn run_with_counter(input: &str) -> Result<Option<Value>> {
+        COUNTER.with(|counter| counter.set(0));
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let native = NativeFunction::new("next", Arity::Fixed(0), next);
+        scope
+            .borrow_mut()
+            .declare("next", Value::NativeFunction(Rc::new(native)), false);
+        evaluate(Rc::new(parse(tokenize(input)?)?), scope)
+    }
+
+    #[test]
+    fn test_arguments_are_evaluated_left_to_right() -> Result<()> {
+        let result = run_with_counter("fn f(a, b, c) = (a, b, c); f(next(), next(), next())")?;
+        let expected: Rc<[Value]> = Rc::new([Value::Number(1), Value::Number(2), Value::Number(3)]);
+        assert_eq!(result, Some(Value::Tuple(expected)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_failing_argument_stops_later_arguments() {
+        let result = run_with_counter("fn f(a, b, c) = a; f(next(), missing, next())");
+        assert!(result.is_err());
+        assert_eq!(COUNTER.with(Cell::get), 1);
+    }
+
+    #[test]
+    fn test_interrupt_stops_running_loop() {
+        // The budget only ends the loop if the interrupt is missed, failing instead of hanging
+        let config = Config {
+            iteration_budget: Some(Cell::new(10_000_000)),
+            ..Config::default()
+        };
+        let interrupt = Arc::clone(&config.interrupt);
+        let setter = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            interrupt.store(true, Ordering::Relaxed);
+        });
+
+        let started = Instant::now();
+        let result = run_with(
+            "let mut i = 0; while true { i = i + 1; i = i % 1000 }",
+            &config,
+        );
+        setter.join().unwrap();
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Runtime error: Interrupted"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_interrupt_stops_at_function_call() -> Result<()> {
+        let config = Config::default();
+        config.interrupt.store(true, Ordering::Relaxed);
+        let evaluate = |input| {
+            let program = parse(tokenize(input)?)?;
+            evaluate_with(&program, Rc::new(RefCell::new(Scope::new(None))), &config)
+        };
+        assert!(matches!(
+            evaluate("fn f() = 1; f()"),
+            Err(MovaError::Runtime(RuntimeError::Interrupted))
+        ));
+        assert!(evaluate("1 + 2").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interrupt_is_cleared_when_a_run_starts() {
+        let config = Config::default();
+        config.interrupt.store(true, Ordering::Relaxed);
+        assert_eq!(
+            run_with("fn f() = 1; f()", &config).unwrap(),
+            Some(Value::Number(1))
+        );
+        assert!(!config.interrupt.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_do_evaluates_in_child_scope() {
+        assert_eq!(
+            run("let x = 1; do { let x = 2; x }").unwrap(),
+            Some(Value::Number(2))
+        );
+        assert_eq!(
+            run("let x = 1; do { let x = 2 }; x").unwrap(),
+            Some(Value::Number(1))
+        );
+        assert_eq!(run("let x = 1; do x + 1").unwrap(), Some(Value::Number(2)));
+        assert!(run("do { let y = 2 }; y").is_err());
+    }
+
+    #[test]
+    fn test_operator_not_defined_for_operand_types() {
+        let cases = [
+            (
+                "true + 1",
+                "Operator '+' is not defined for boolean and number",
+            ),
+            (
+                "1 - false",
+                "Operator '-' is not defined for number and boolean",
+            ),
+            (
+                "(1, 2) * 2",
+                "Operator '*' is not defined for tuple and number",
+            ),
+            (
+                "1 < true",
+                "Operator '<' is not defined for number and boolean",
+            ),
+            (
+                "true & false",
+                "Operator '&' is not defined for boolean and boolean",
+            ),
+            (
+                "1 << true",
+                "Operator '<<' is not defined for number and boolean",
+            ),
+            (
+                "true && 1",
+                "Operator '&&' is not defined for boolean and number",
+            ),
+            (
+                "let x = true; let r = &x; r + 1",
+                "Operator '+' is not defined for boolean and number",
+            ),
+        ];
+        for (input, message) in cases {
+            match run(input) {
+                Ok(val) => panic!("{input} should have failed but succeeded with: {:?}", val),
+                Err(e) => assert_eq!(e.to_string(), format!("Runtime error: {message}")),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_operator() -> Result<()> {
+        let options = ParserOptions {
+            precedence: PrecedenceTable::default().with_infix("~", 5, 6),
+            ..ParserOptions::default()
+        };
+        let program = parse_with(tokenize("1 ~ 2")?, options)?;
+        let result = evaluate(Rc::new(program), Rc::new(RefCell::new(Scope::new(None))));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Runtime error: Unknown operator '~'"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_builds_array() {
+        let expected = vec![Value::Number(0), Value::Number(1), Value::Number(2)];
+        assert_eq!(
+            run("range(0, 3)").unwrap(),
+            Some(Value::Array(Rc::new(RefCell::new(expected))))
+        );
+        assert_eq!(
+            run("range(2, 2)").unwrap(),
+            Some(Value::Array(Rc::new(RefCell::new(Vec::new()))))
+        );
+        assert_eq!(
+            run("range(0 - 1, 1)").unwrap().unwrap().to_string(),
+            "[-1, 0]"
+        );
+    }
+
+    #[test]
+    fn test_range_rejects_invalid_arguments() {
+        let cases = [
+            ("range(3, 1)", "range start 3 is greater than end 1"),
+            (
+                "range(0, true)",
+                "range expects two numbers but received '0' and 'true'",
+            ),
+            ("range(1)", "Expected 2 arguments but received 1"),
+        ];
+        for (input, message) in cases {
+            match run(input) {
+                Ok(val) => panic!("{input} should have failed but succeeded with: {:?}", val),
+                Err(e) => assert!(e.to_string().contains(message), "Error message was: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_length_is_capped() {
+        let config = Config {
+            max_range_length: 10,
+            ..Config::default()
+        };
+        assert!(run_with("range(0, 10)", &config).is_ok());
+        assert_eq!(
+            run_with("range(0, 11)", &config).unwrap_err().to_string(),
+            "Runtime error: Range of 11 elements exceeds the limit of 10"
+        );
+        assert!(run("range(0 - 2147483647, 2147483647)").is_err());
+    }
+
+    #[test]
+    fn test_push_and_pop_round_trip() {
+        let input = "
+            let mut numbers = range(0, 2);
+            let length = push(&mut numbers, 5);
+            let last = pop(&mut numbers);
+            let second = pop(&mut numbers);
+            push(&mut numbers, last + second);
+            (length, numbers)
+        ";
+        assert_eq!(run(input).unwrap().unwrap().to_string(), "(3, [0, 6])");
+        assert_eq!(
+            run("let mut empty = range(0, 0); pop(&mut empty)")
+                .unwrap_err()
+                .to_string(),
+            "Runtime error: Cannot pop from an empty array\n  in pop"
+        );
+    }
+
+    #[test]
+    fn test_push_changes_array_in_place() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let program = parse(tokenize(
+            "let mut numbers = range(0, 2); let copy = clone(numbers)",
+        )?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+        let Value::Array(before) = scope.borrow().read("numbers")? else {
+            panic!("Expected an array");
+        };
+
+        let program = parse(tokenize(
+            "push(&mut numbers, 2); pop(&mut numbers); push(&mut numbers, 3)",
+        )?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+        let Value::Array(after) = scope.borrow().read("numbers")? else {
+            panic!("Expected an array");
+        };
+        assert!(Rc::ptr_eq(&before, &after));
+        assert_eq!(after.borrow().len(), 3);
+        assert_eq!(scope.borrow().read("copy")?.to_string(), "[0, 1]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_replaces_element_in_bounds() {
+        let input = "
+            let mut numbers = range(0, 3);
+            let previous = set(&mut numbers, 1, 7);
+            (previous, numbers)
+        ";
+        assert_eq!(run(input).unwrap().unwrap().to_string(), "(1, [0, 7, 2])");
+
+        for index in ["3", "0 - 1"] {
+            let input = format!("let mut numbers = range(0, 3); set(&mut numbers, {index}, 7)");
+            assert!(matches!(
+                run(&input),
+                Err(MovaError::Backtrace { error, .. })
+                    if matches!(*error, MovaError::Runtime(RuntimeError::IndexOutOfBounds { length: 3, .. }))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_array_mutation_requires_mutable_borrow() {
+        let errors = [
+            (
+                "let numbers = range(0, 1); push(&numbers, 1)",
+                "Cannot assign to an immutable reference",
+            ),
+            (
+                "let mut numbers = range(0, 1); push(numbers, 1)",
+                "expected a mutable reference to an array",
+            ),
+            ("let mut n = 1; pop(&mut n)", "received a reference to '1'"),
+            (
+                "let mut numbers = range(0, 1); let r = &numbers; push(&mut numbers, 1)",
+                "Unable to borrow mutably because it is already borrowed",
+            ),
+        ];
+        for (input, message) in errors {
+            let error = run(input).unwrap_err().to_string();
+            assert!(error.contains(message), "{input}: {error}");
+        }
+    }
+
+    #[test]
+    fn test_index_reads_nested_arrays() {
+        let program = "let mut m = range(0, 2); set(&mut m, 1, range(5, 8)); m[1][2] + m[0]";
+        assert_eq!(run(program).unwrap(), Some(Value::Number(7)));
+        assert_eq!(
+            run("let t = (1, (2, 3)); let r = &t; r[1][0] + t[0]").unwrap(),
+            Some(Value::Number(3))
+        );
+    }
+
+    #[test]
+    fn test_index_rejects_invalid_targets() {
+        let errors = [
+            ("let n = 1; n[0]", "Cannot index into a number"),
+            ("let m = range(0, 2); m[0][0]", "Cannot index into a number"),
+            ("let m = range(0, 2); m[2]", "Index 2 is out of bounds"),
+            ("let m = range(0, 2); m[true]", "Expected a number as index"),
+        ];
+        for (input, message) in errors {
+            let error = run(input).unwrap_err().to_string();
+            assert!(error.contains(message), "{input}: {error}");
+        }
+    }
+
+    #[test]
+    fn test_max_accepts_one_or_more_arguments() {
+        assert_eq!(run("max(3)").unwrap(), Some(Value::Number(3)));
+        assert_eq!(run("max(3, 9, 0 - 4)").unwrap(), Some(Value::Number(9)));
+        assert_eq!(
+            run("max()").unwrap_err().to_string(),
+            "Runtime error: Expected at least 1 arguments but received 0"
+        );
+        assert!(run("max(1, true)").is_err());
+    }
+
+    #[test]
+    fn test_compare_orders_numbers() {
+        assert_eq!(run("compare(1, 2)").unwrap(), Some(Value::Number(-1)));
+        assert_eq!(run("compare(2, 2)").unwrap(), Some(Value::Number(0)));
+        assert_eq!(run("compare(3, 2)").unwrap(), Some(Value::Number(1)));
+        assert_eq!(
+            run("compare(1, true)").unwrap_err().to_string(),
+            "Runtime error: Cannot compare '1' with 'true'\n  in compare"
+        );
+        assert!(run("compare(1)").is_err());
+    }
+
+    #[test]
+    fn test_function_can_be_called_repeatedly() {
+        let input = "
+            fn fib(n) = if n < 2 { n } else { fib(n - 1) + fib(n - 2) }
+            fib(10) + 1
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(56)));
+    }
+
+    #[test]
+    fn test_calling_function_leaves_it_bound() {
+        let input = "
+            fn one() = 1
+            one() + one()
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(2)));
+    }
+
+    #[test]
+    fn test_using_function_by_name_moves_it() {
+        let input = "
+            fn one() = 1
+            let other = one
+            one()
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Unable to use 'one' because it is moved"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_drop_moves_value() {
+        let input = "
+            let x = 5
+            drop(x)
+            x
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Unable to use 'x' because it is moved"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_drop_releases_borrow() {
+        let input = "
+            let mut x = 1
+            let r = &mut x
+            drop(r)
+            x = 2
+            x
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(2)));
+    }
+
+    #[test]
+    fn test_chained_assignment_assigns_right_to_left() {
+        let input = "
+            let mut a = 0;
+            let b;
+            a = b = 1 + 2;
+            (a, b)
+        ";
+        assert_eq!(run(input).unwrap().unwrap().to_string(), "(3, 3)");
+        assert_eq!(
+            run("let mut a = 0; let mut b = 0; a = b = (1, 2)")
+                .unwrap_err()
+                .to_string(),
+            "Runtime error: Cannot assign '(1, 2)' to several variables, since it would be moved more than once"
+        );
+    }
+
+    #[test]
+    fn test_cannot_drop_borrowed_value() {
+        let input = "
+            let x = 1
+            let r = &x
+            drop(x)
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Unable to move 'x' because it is borrowed (active borrows: 1)"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_drop_can_be_shadowed() {
+        let input = "
+            fn drop(value) = value + 1
+            let x = 1
+            drop(x) + x
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(3)));
+    }
+
+    #[test]
+    fn test_cannot_create_self_reference() {
+        let input = "
+            let mut x = 1
+            x = &x
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string().contains("Cyclic reference"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_cannot_create_transitive_reference_cycle() {
+        let input = "
+            let mut x = 1
+            let y = &x
+            x = &y
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string().contains("Cyclic reference"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_backtrace_lists_nested_calls() {
+        let input = "
+            fn h(x) = x / 0
+            fn g(x) = h(x) + 1
+            fn f(x) = g(x) * 2
+            f(1)
+        ";
+        let error = run(input).unwrap_err();
+        assert_eq!(error.frames(), ["f", "g", "h"]);
+        assert_eq!(
+            error.to_string(),
+            "Runtime error: Division by zero\n  in f -> g -> h"
+        );
+    }
+
+    #[test]
+    fn test_argument_errors_are_not_attributed_to_callee() {
+        let input = "
+            fn f(x) = x
+            f(1 / 0)
+        ";
+        let error = run(input).unwrap_err();
+        assert!(error.frames().is_empty());
+    }
+
+    #[test]
+    fn test_cannot_return_reference_to_block_local_value() {
+        let input = "
+            let r = {
+                let local = 1
+                &local
+            }
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Cannot return reference to block-local value"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_can_return_reference_to_value_outside_block() {
+        let input = "
+            let outer = 5
+            let r = {
+                let inner = 1
+                &outer
+            }
+            *r
+        ";
+        assert_eq!(run(input).unwrap(), Some(Value::Number(5)));
+
+        // The reference may leave the inner block, but not the block that owns its target
+        let input = "{ let middle = 2; let r = { &middle }; *r }";
+        assert_eq!(run(input).unwrap(), Some(Value::Number(2)));
+        let result = run("let r = { let middle = 2; { &middle } }");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Runtime error: Cannot return reference to block-local value"
+        );
+    }
+
+    #[test]
+    fn test_prefixed_number_literals() {
+        assert_eq!(run("0b1010").unwrap(), Some(Value::Number(10)));
+        assert_eq!(run("0xff").unwrap(), Some(Value::Number(255)));
+        assert_eq!(run("0o10 + 0x10").unwrap(), Some(Value::Number(24)));
+    }
+
+    #[test]
+    fn test_if_without_else_as_statement() {
+        let input = "
+            let mut x = 0
+            if x > 5 { x = 100 }
+            if x < 5 { x = x + 1 }
+            {
+                if false { x = 50 }
+                x = x + 2
+            }
+            x
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(3)));
+    }
+
+    #[test]
+    fn test_if_without_else_yields_no_value_when_false() {
+        let result = run("if false { 1 }");
+        assert_eq!(result.unwrap(), None);
+    }
+
+    #[test]
+    fn test_zero_argument_function() {
+        let input = "
+            fn f() = 42
+            f() + f()
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(84)));
+    }
+
+    #[test]
+    fn test_zero_argument_function_rejects_arguments() {
+        let input = "
+            fn f() = 42
+            f(1)
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Expected 0 arguments but received 1"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_missing_arguments() {
+        let input = "
+            fn add(a, b) = a + b
+            add(0 - 1)
+        ";
+        let result = run(input);
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert!(
+                e.to_string()
+                    .contains("Expected 2 arguments but received 1"),
+                "Error message was: {}",
+                e
+            ),
+        }
+    }
+
+    #[test]
+    fn test_function_body_block_returns_trailing_expression() {
+        let input = "
+            fn f(x) = { let y = x + 1 y * 2 }
+            f(3)
+        ";
+        let result = run(input);
+        assert_eq!(result.unwrap(), Some(Value::Number(8)));
+    }
+
+    #[test]
+    fn test_return_from_if_inside_function() {
+        let input = "
+            fn f(condition) = { if condition { return 1 } 2 }
+            (f(true), f(false))
+        ";
+        let result = run(input).unwrap();
+        assert_eq!(
+            result.map(|value| value.to_string()),
+            Some("(1, 2)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_return_leaves_nested_blocks_and_loops() {
+        let input = "
+            fn first_above(limit) = {
+                let mut n = 0;
+                while true {
+                    { if n * n > limit { return n } };
+                    n = n + 1
+                }
+            }
+            let x = first_above(10);
+            x + 1
+        ";
+        assert_eq!(run(input).unwrap(), Some(Value::Number(5)));
+    }
+
+    #[test]
+    fn test_return_without_value() {
+        let result = run("fn f() = { return; 1 }; f()");
+        assert!(result.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_return_outside_function() {
+        match run("return 1; 2") {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Runtime error: 'return' used outside of a function"
+            ),
+        }
+    }
+
+    #[test]
+    fn test_unterminated_function_body() {
+        let result = run("fn f(x) = { let y = x + 1 y * 2");
+        match &result {
+            Ok(val) => panic!("Test should have failed but succeeded with: {:?}", val),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Parser error at 1:32: Expected block opened on line 1 to be closed"
+            ),
+        }
+    }
+
+    #[test]
+    fn test_memoized_function_runs_once_per_argument() {
+        let input = "
+            let mut calls = 0;
+            fn double(n) = { calls = calls + 1; n * 2 };
+            let double = memoize(double);
+            let total = double(1) + double(1) + double(2) + double(1) + double(2);
+            (total, calls)
+        ";
+        let expected = Value::Tuple(vec![Value::Number(14), Value::Number(2)].into());
+        assert_eq!(run(input).unwrap(), Some(expected));
+    }
+
+    #[test]
+    fn test_memoized_recursion() {
+        let input = "
+            fn fib(n) = if n < 2 { n } else { fib(n - 1) + fib(n - 2) };
+            let fib = memoize(fib);
+            fib(30)
+        ";
+        assert_eq!(run(input).unwrap(), Some(Value::Number(832_040)));
+    }
+
+    #[test]
+    fn test_memoized_function_bypasses_cache_for_other_arguments() {
+        let input = "
+            let mut calls = 0;
+            fn first(t) = { calls = calls + 1; t };
+            let first = memoize(first);
+            first((1, 2));
+            first((1, 2));
+            calls
+        ";
+        assert_eq!(run(input).unwrap(), Some(Value::Number(2)));
+    }
+
+    #[test]
+    fn test_memoize_requires_function() {
+        let result = run("memoize(1)");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Runtime error: Invalid argument: expected a function but received '1'\n  in memoize"
+        );
+    }
+
+    #[test]
+    fn test_registered_operator_overload() {
+        let mut config = Config::default();
+        config.operators.register(
+            "+",
+            "boolean",
+            "boolean",
+            NativeFunction::new("concat", Arity::Fixed(2), |arguments| {
+                Ok(Value::Tuple(arguments.to_vec().into()))
+            }),
+        );
+
+        let expected = Value::Tuple(vec![Value::Boolean(true), Value::Boolean(false)].into());
+        assert_eq!(
+            run_with("let a = true; a + false", &config).unwrap(),
+            Some(expected)
+        );
+        assert_eq!(run_with("1 + 2", &config).unwrap(), Some(Value::Number(3)));
+        assert_eq!(
+            run_with("1 + true", &config).unwrap_err().to_string(),
+            "Runtime error: Operator '+' is not defined for number and boolean"
+        );
+    }
+
+    fn run_with_input(input: &str, lines: &'static str) -> Result<Option<Value>> {
+        let config = Config {
+            input: Input(Rc::new(RefCell::new(std::io::Cursor::new(lines)))),
+            ..Config::default()
+        };
+        run_with(input, &config)
+    }
+
+    #[test]
+    fn test_to_number() {
+        assert_eq!(
+            run_with_input("to_number(input()) + 1", "41\n").unwrap(),
+            Some(Value::Number(42))
+        );
+        assert_eq!(run("to_number(-3)").unwrap(), Some(Value::Number(-3)));
+        assert_eq!(
+            run_with_input("to_number(input())", "abc\n")
+                .unwrap_err()
+                .to_string(),
+            "Runtime error: Invalid argument: cannot convert 'abc' to a number\n  in to_number"
+        );
+        assert_eq!(
+            run("to_number(true)").unwrap_err().to_string(),
+            "Runtime error: Invalid argument: cannot convert boolean 'true' to a number\n  in to_number"
+        );
+    }
+
+    #[test]
+    fn test_to_bool() {
+        assert_eq!(run("to_bool(0)").unwrap(), Some(Value::Boolean(false)));
+        assert_eq!(run("to_bool(-2)").unwrap(), Some(Value::Boolean(true)));
+        assert_eq!(
+            run_with_input("to_bool(input())", "true\n").unwrap(),
+            Some(Value::Boolean(true))
+        );
+        assert_eq!(
+            run_with_input("to_bool(input())", "false\n").unwrap(),
+            Some(Value::Boolean(false))
+        );
+        assert_eq!(
+            run_with_input("to_bool(input())", "yes\n")
+                .unwrap_err()
+                .to_string(),
+            "Runtime error: Invalid argument: cannot convert 'yes' to a boolean\n  in to_bool"
+        );
+        assert!(run("to_bool((1, 2))").is_err());
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(
+            run("to_string(12)").unwrap(),
+            Some(Value::String("12".into()))
+        );
+        assert_eq!(
+            run("to_string((1, false))").unwrap(),
+            Some(Value::String("(1, false)".into()))
+        );
+        assert_eq!(
+            run("to_number(to_string(7)) == 7").unwrap(),
+            Some(Value::Boolean(true))
+        );
+    }
 }
@@ -0,0 +1,62 @@
+use std::{cell::RefCell, collections::HashMap, fmt};
+
+use crate::interpreter::data::Value;
+
+/// An argument that can be part of a cache key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Number(i32),
+    Boolean(bool),
+}
+
+/// A function wrapped by `memoize`, which remembers its results by argument
+pub struct Memoized {
+    pub function: Value,
+    cache: RefCell<HashMap<Vec<Key>, Value>>,
+}
+
+impl Memoized {
+    pub fn new(function: Value) -> Self {
+        Self {
+            function,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Key for a call, or `None` if an argument is neither a number nor a boolean
+    fn key(arguments: &[Value]) -> Option<Vec<Key>> {
+        arguments
+            .iter()
+            .map(|argument| match argument {
+                Value::Number(n) => Some(Key::Number(*n)),
+                Value::Boolean(b) => Some(Key::Boolean(*b)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The result of an earlier call with the same arguments
+    pub fn get(&self, arguments: &[Value]) -> Option<Value> {
+        let key = Self::key(arguments)?;
+        self.cache.borrow().get(&key).cloned()
+    }
+
+    /// Remembers a result, unless it would be moved out by a later call and so
+    /// cannot be handed out more than once
+    pub fn insert(&self, arguments: &[Value], result: &Value) {
+        if let Some(key) = Self::key(arguments)
+            && result.is_copy()
+        {
+            self.cache.borrow_mut().insert(key, result.clone());
+        }
+    }
+}
+
+impl fmt::Debug for Memoized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Memoized")
+            .field("function", &self.function)
+            .field("cached", &self.cache.borrow().len())
+            .finish()
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+use crate::{
+    error::{MovaError, Result, RuntimeError},
+    interpreter::data::Value,
+};
+
+/// How many arguments a native function accepts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Fixed(usize),
+    Variadic { min: usize },
+}
+
+impl Arity {
+    /// Checks an argument count against this arity
+    pub fn check(self, received: usize) -> Result<()> {
+        match self {
+            Arity::Fixed(expected) if received != expected => {
+                Err(MovaError::Runtime(RuntimeError::InvalidArgumentCount {
+                    expected,
+                    received,
+                }))
+            }
+            Arity::Variadic { min } if received < min => {
+                Err(MovaError::Runtime(RuntimeError::TooFewArguments {
+                    minimum: min,
+                    received,
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A function implemented in Rust that receives already evaluated arguments
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Arity,
+    pub function: fn(&[Value]) -> Result<Value>,
+}
+
+impl NativeFunction {
+    pub fn new(name: &str, arity: Arity, function: fn(&[Value]) -> Result<Value>) -> Self {
+        Self {
+            name: name.into(),
+            arity,
+            function,
+        }
+    }
+
+    /// Checks the arity and then runs the function
+    pub fn call(&self, arguments: &[Value]) -> Result<Value> {
+        self.arity.check(arguments.len())?;
+        (self.function)(arguments).map_err(|error| error.in_frame(&self.name))
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
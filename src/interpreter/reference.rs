@@ -25,8 +25,12 @@ impl Reference {
         let mut data = slot.borrow_mut();
 
         if let Value::Moved = data.value {
+            return Err(MovaError::Runtime(RuntimeError::UnableToBorrowBecauseMoved));
+        }
+
+        if let Value::Uninitialized = data.value {
             return Err(MovaError::Runtime(
-                RuntimeError::UnableToBorrowBecauseMoved,
+                RuntimeError::UnableToBorrowBecauseUninitialized,
             ));
         }
 
@@ -61,6 +65,22 @@ impl Reference {
         }
     }
 
+    /// Whether following this reference, and any references stored behind it, leads to `slot`
+    pub fn reaches(&self, slot: &Slot) -> bool {
+        if Rc::ptr_eq(&self.slot, slot) {
+            return true;
+        }
+
+        match self.slot.try_borrow() {
+            Ok(data) => match &data.value {
+                Value::Reference(inner) => inner.reaches(slot),
+                _ => false,
+            },
+            // A slot that cannot be inspected right now is conservatively treated as a cycle
+            Err(_) => true,
+        }
+    }
+
     pub fn read(&self) -> Result<Ref<'_, Data>> {
         let data = self.slot.borrow();
         if let State::Deallocated = data.state {
@@ -71,6 +91,21 @@ impl Reference {
         Ok(data)
     }
 
+    /// Follows references stored behind this one down to the first value that is not a reference
+    pub fn resolve_value(&self) -> Result<Value> {
+        let mut visited = vec![Rc::clone(&self.slot)];
+        let mut value = self.read()?.value.clone();
+
+        while let Value::Reference(inner) = value {
+            if visited.iter().any(|slot| Rc::ptr_eq(slot, &inner.slot)) {
+                return Err(MovaError::Runtime(RuntimeError::CyclicReference));
+            }
+            visited.push(Rc::clone(&inner.slot));
+            value = inner.read()?.value.clone();
+        }
+        Ok(value)
+    }
+
     pub fn write(&self) -> Result<RefMut<'_, Data>> {
         let data = self.slot.borrow_mut();
 
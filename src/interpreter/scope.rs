@@ -1,31 +1,146 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt, mem,
+    rc::{Rc, Weak},
+};
 
 use crate::{
     error::{MovaError, Result, RuntimeError},
     interpreter::data::{Data, Slot, State, Value},
 };
 
+/// How many ancestor lookups a scope performs before it starts caching them, so that
+/// short-lived scopes such as function calls don't pay for a cache they won't reuse
+const CACHE_THRESHOLD: usize = 16;
+
+/// The name of a local, sharing the `Rc` the parser interned for it while still being
+/// looked up by `&str`
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Name(Rc<String>);
+
+impl Name {
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::borrow::Borrow<str> for Name {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&Rc<String>> for Name {
+    fn from(name: &Rc<String>) -> Self {
+        Name(Rc::clone(name))
+    }
+}
+
+impl From<&str> for Name {
+    fn from(name: &str) -> Self {
+        Name(Rc::new(name.to_string()))
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Slots previously found in ancestor scopes
+#[derive(Clone, Debug, Default)]
+struct Cache {
+    generation: usize,
+    lookups: usize,
+    slots: HashMap<Name, Slot>,
+}
+
+/// The locals of a scope and their contents at one point in time
+#[derive(Clone, Debug)]
+pub struct ScopeSnapshot {
+    locals: Vec<(Name, Slot, Data)>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Scope {
-    parent: Option<Rc<RefCell<Scope>>>,
-    locals: HashMap<String, Slot>,
+    /// Held weakly, since a function stored in an ancestor would otherwise form a cycle with
+    /// its own definition scope
+    parent: Option<Weak<RefCell<Scope>>>,
+    /// Definition scopes of the functions declared here or in a closed descendant, which
+    /// keep this scope alive once it is closed
+    closures: Vec<Weak<RefCell<Scope>>>,
+    /// Closed scopes on a definition scope's chain, kept alive so that the function can
+    /// still look names up through them
+    enclosing: Vec<Rc<RefCell<Scope>>>,
+    locals: HashMap<Name, Slot>,
+    cache: RefCell<Cache>,
+    /// Shared by every scope in the tree and bumped on each declaration, which may shadow
+    /// a cached slot
+    generation: Rc<Cell<usize>>,
 }
 
 impl Scope {
     pub fn new(parent: Option<Rc<RefCell<Scope>>>) -> Self {
+        let generation = match &parent {
+            Some(p) => Rc::clone(&p.borrow().generation),
+            None => Rc::new(Cell::new(0)),
+        };
         Self {
-            parent,
+            parent: parent.as_ref().map(Rc::downgrade),
+            closures: Vec::new(),
+            enclosing: Vec::new(),
             locals: HashMap::new(),
+            cache: RefCell::new(Cache::default()),
+            generation,
         }
     }
 
-    pub fn declare(&mut self, name: &str, value: Value, is_mutable: bool) {
+    pub fn declare(&mut self, name: impl Into<Name>, value: Value, is_mutable: bool) {
         let slot = Rc::new(RefCell::new(Data {
             value,
             state: State::Free,
             is_mutable,
         }));
         self.locals.insert(name.into(), slot);
+        self.generation.set(self.generation.get() + 1);
+    }
+
+    /// Like `declare`, but refuses to replace a variable of this scope while it is borrowed,
+    /// since its references would keep counting against a slot no name refers to anymore.
+    /// Binding the reference itself, as in `let x = &x`, is allowed.
+    pub fn redeclare(
+        &mut self,
+        name: impl Into<Name>,
+        value: Value,
+        is_mutable: bool,
+    ) -> Result<()> {
+        let name = name.into();
+        self.check_redeclare(name.as_str(), &value)?;
+        self.declare(name, value, is_mutable);
+        Ok(())
+    }
+
+    /// Fails the way `redeclare` would, without declaring anything
+    pub fn check_redeclare(&self, name: &str, value: &Value) -> Result<()> {
+        if let Some(slot) = self.locals.get(name)
+            && matches!(
+                slot.borrow().state,
+                State::Borrowed(_) | State::MutablyBorrowed
+            )
+            && !matches!(value, Value::Reference(r) if Rc::ptr_eq(&r.slot, slot))
+        {
+            return Err(MovaError::Runtime(
+                RuntimeError::CannotShadowBorrowedVariable(name.to_string()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the slot belongs to this scope itself rather than to one of its ancestors
+    pub fn owns(&self, slot: &Slot) -> bool {
+        self.locals.values().any(|local| Rc::ptr_eq(local, slot))
     }
 
     /// This ensures that any lingering references to these variables become invalid
@@ -37,42 +152,235 @@ impl Scope {
         });
     }
 
+    /// Remembers the definition scope of a function declared here, which must keep this scope
+    /// alive if the function outlives it
+    pub fn add_closure(&mut self, definition_scope: &Rc<RefCell<Scope>>) {
+        // Drop the functions that are gone before growing, so the list stays proportional
+        // to the live ones
+        if self.closures.len() == self.closures.capacity() {
+            self.closures.retain(|closure| closure.strong_count() > 0);
+        }
+        self.closures.push(Rc::downgrade(definition_scope));
+    }
+
+    /// Invalidates a scope whose block or call has ended. Functions declared in it that are
+    /// still around keep it alive, and hand themselves to its parent in turn, so that
+    /// lookups from them still reach the scopes outside.
+    pub fn close(scope: &Rc<RefCell<Scope>>) {
+        let (closures, parent) = {
+            let mut this = scope.borrow_mut();
+            this.invalidate();
+            let parent = this.parent.as_ref().and_then(Weak::upgrade);
+            (mem::take(&mut this.closures), parent)
+        };
+        for closure in closures.iter().filter_map(Weak::upgrade) {
+            closure.borrow_mut().enclosing.push(Rc::clone(scope));
+            if let Some(parent) = &parent {
+                parent.borrow_mut().add_closure(&closure);
+            }
+        }
+    }
+
+    /// Captures the locals so that later moves, borrows and declarations can be rolled back
+    pub fn snapshot(&self) -> ScopeSnapshot {
+        let locals = self
+            .locals
+            .iter()
+            .map(|(name, slot)| {
+                let data = slot.borrow();
+                // Arrays are changed in place, so the snapshot keeps its own copy of them
+                let data = Data {
+                    value: data.value.detach(),
+                    ..data.clone()
+                };
+                (name.clone(), Rc::clone(slot), data)
+            })
+            .collect();
+        ScopeSnapshot { locals }
+    }
+
+    /// Puts back the locals captured by `snapshot`, including their ownership state
+    pub fn restore(&mut self, snapshot: ScopeSnapshot) {
+        self.locals.clear();
+        for (name, slot, data) in snapshot.locals {
+            // The slot is reused so references taken before the snapshot still point at it
+            *slot.borrow_mut() = data;
+            self.locals.insert(name, slot);
+        }
+        self.generation.set(self.generation.get() + 1);
+    }
+
+    /// Renders every local with its ownership state, followed by the parent scopes
+    pub fn dump(&self) -> String {
+        let mut output = String::new();
+        self.dump_into(&mut output, 0);
+        output
+    }
+
+    fn dump_into(&self, output: &mut String, depth: usize) {
+        let indentation = "  ".repeat(depth);
+        let mut names: Vec<&Name> = self.locals.keys().collect();
+        names.sort();
+
+        for name in names {
+            let data = self.locals[name].borrow();
+            let borrows = match data.state {
+                State::Borrowed(count) => count,
+                _ => 0,
+            };
+            output.push_str(&format!(
+                "{indentation}{name} = {} [borrows: {borrows}, moved: {}, mutably borrowed: {}]\n",
+                data.value,
+                matches!(data.value, Value::Moved),
+                matches!(data.state, State::MutablyBorrowed),
+            ));
+        }
+
+        if let Some(parent) = self.parent.as_ref().and_then(Weak::upgrade) {
+            parent.borrow().dump_into(output, depth + 1);
+        }
+    }
+
     pub fn find_slot(&self, name: &str) -> Result<Slot> {
+        self.find_slot_counting_hops(name).map(|(slot, _)| slot)
+    }
+
+    /// Also reports how many scopes had to be visited, so that only lookups which were
+    /// actually expensive get cached
+    fn find_slot_counting_hops(&self, name: &str) -> Result<(Slot, usize)> {
         if let Some(slot) = self.locals.get(name) {
-            return Ok(Rc::clone(slot));
+            return Ok((Rc::clone(slot), 0));
+        }
+
+        let Some(parent) = &self.parent else {
+            return Err(MovaError::Runtime(RuntimeError::UnableToResolve(
+                name.to_string(),
+            )));
+        };
+        let parent = parent.upgrade().ok_or_else(|| {
+            MovaError::Runtime(RuntimeError::ParentScopeDropped(name.to_string()))
+        })?;
+
+        let mut cache = self.cache.borrow_mut();
+        if cache.generation != self.generation.get() {
+            cache.slots.clear();
+            cache.generation = self.generation.get();
+        }
+        if let Some(slot) = cache.slots.get(name) {
+            return Ok((Rc::clone(slot), 0));
+        }
+
+        let (slot, hops) = parent.borrow().find_slot_counting_hops(name)?;
+        cache.lookups += 1;
+        if hops > 0 && cache.lookups > CACHE_THRESHOLD {
+            cache.slots.insert(name.into(), Rc::clone(&slot));
+        }
+        Ok((slot, hops + 1))
+    }
+
+    /// Finds `name` like `find_slot` and also reports in how many scopes up it is declared,
+    /// with 0 for this scope. The value is returned as stored, even if moved or uninitialized,
+    /// so that tooling can show every binding.
+    pub fn resolve_with_depth(&self, name: &str) -> Result<(Value, usize)> {
+        self.resolve_shadowed(name, 0)
+    }
+
+    /// Like `resolve_with_depth`, but passes over the first `skip` bindings of `name` to reach
+    /// those they shadow
+    pub fn resolve_shadowed(&self, name: &str, skip: usize) -> Result<(Value, usize)> {
+        let (slot, depth) = self.find_slot_with_depth(name, skip, 0)?;
+        let value = slot.borrow().value.clone();
+        Ok((value, depth))
+    }
+
+    /// Walks the ancestors without the lookup cache, which does not know where a slot came from
+    fn find_slot_with_depth(&self, name: &str, skip: usize, depth: usize) -> Result<(Slot, usize)> {
+        let skip = match self.locals.get(name) {
+            Some(slot) if skip == 0 => return Ok((Rc::clone(slot), depth)),
+            Some(_) => skip - 1,
+            None => skip,
+        };
+
+        let Some(parent) = &self.parent else {
+            return Err(MovaError::Runtime(RuntimeError::UnableToResolve(
+                name.to_string(),
+            )));
+        };
+        let parent = parent.upgrade().ok_or_else(|| {
+            MovaError::Runtime(RuntimeError::ParentScopeDropped(name.to_string()))
+        })?;
+        parent.borrow().find_slot_with_depth(name, skip, depth + 1)
+    }
+
+    /// Reads a value without taking ownership of it
+    pub fn read(&self, name: &str) -> Result<Value> {
+        let slot = self.find_slot(name)?;
+        let data = slot.borrow();
+
+        if let State::Deallocated = data.state {
+            return Err(MovaError::Runtime(
+                RuntimeError::UnableToUseBecauseDeallocated(name.to_string()),
+            ));
         }
 
-        match &self.parent {
-            Some(p) => p.borrow().find_slot(name),
-            None => Err(MovaError::Runtime(RuntimeError::UnableToResolve(name.to_string()))),
+        if matches!(data.state, State::MutablyBorrowed) {
+            return Err(MovaError::Runtime(
+                RuntimeError::UnableToUseBecauseMutablyBorrowed(name.to_string()),
+            ));
+        }
+
+        match &data.value {
+            Value::Moved => Err(MovaError::Runtime(RuntimeError::UnableToUseBecauseMoved(
+                name.to_string(),
+            ))),
+            Value::Uninitialized => Err(MovaError::Runtime(
+                RuntimeError::UseOfPossiblyUninitializedVariable(name.to_string()),
+            )),
+            value => Ok(value.clone()),
         }
     }
 
     pub fn resolve(&mut self, name: &str) -> Result<Value> {
+        self.access(name, false)
+    }
+
+    /// Moves a value out of its binding, even if it could be copied
+    pub fn take(&mut self, name: &str) -> Result<Value> {
+        self.access(name, true)
+    }
+
+    fn access(&mut self, name: &str, always_move: bool) -> Result<Value> {
         let slot = self.find_slot(name)?;
         let mut data = slot.borrow_mut();
 
         if let State::Deallocated = data.state {
-            return Err(MovaError::Runtime(RuntimeError::UnableToUseBecauseDeallocated(name.to_string())));
+            return Err(MovaError::Runtime(
+                RuntimeError::UnableToUseBecauseDeallocated(name.to_string()),
+            ));
         }
 
         if matches!(data.state, State::MutablyBorrowed) {
-            return Err(MovaError::Runtime(RuntimeError::UnableToMutateBecauseMutablyBorrowed(name.to_string())));
+            return Err(MovaError::Runtime(
+                RuntimeError::UnableToUseBecauseMutablyBorrowed(name.to_string()),
+            ));
         }
 
         match &data.value {
-            Value::Number(_) | Value::Boolean(_) => {
-                Ok(data.value.clone())
-            }
-            Value::Moved => {
-                return Err(MovaError::Runtime(RuntimeError::UnableToUseBecauseMoved(name.to_string())));
-            }
+            value if value.is_copy() && !always_move => Ok(value.clone()),
+            Value::Moved => Err(MovaError::Runtime(RuntimeError::UnableToUseBecauseMoved(
+                name.to_string(),
+            ))),
+            Value::Uninitialized => Err(MovaError::Runtime(
+                RuntimeError::UseOfPossiblyUninitializedVariable(name.to_string()),
+            )),
             _ => {
-                if matches!(
-                    data.state,
-                    State::Borrowed(count) if count > 0
-                ) {
-                    return Err(MovaError::Runtime(RuntimeError::UnableToMutateBecauseImmutablyBorrowed(name.to_string())));
+                if let State::Borrowed(borrows @ 1..) = data.state {
+                    return Err(MovaError::Runtime(
+                        RuntimeError::UnableToMoveBecauseBorrowed {
+                            name: name.to_string(),
+                            borrows,
+                        },
+                    ));
                 }
 
                 Ok(std::mem::replace(&mut data.value, Value::Moved))
@@ -80,3 +388,338 @@ impl Scope {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter::evaluate, lexer::tokenize, parser::parse, runner::run};
+
+    #[test]
+    fn test_dump_shows_moved_value() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let program = parse(tokenize(
+            "
+            fn f(x) = x
+            let g = f
+            ",
+        )?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+
+        let dump = scope.borrow().dump();
+        assert!(
+            dump.contains("f = <moved> [borrows: 0, moved: true, mutably borrowed: false]"),
+            "Dump was: {dump}"
+        );
+        assert!(
+            dump.contains("g = fn(x) [borrows: 0, moved: false, mutably borrowed: false]"),
+            "Dump was: {dump}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_undoes_move() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        evaluate(
+            Rc::new(parse(tokenize("fn f(x) = x; let mut n = 1")?)?),
+            Rc::clone(&scope),
+        )?;
+        let snapshot = scope.borrow().snapshot();
+
+        let program = parse(tokenize("let g = f; let r = &mut n")?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+        assert!(scope.borrow().read("f").is_err());
+        assert_eq!(
+            scope.borrow().find_slot("n")?.borrow().state,
+            State::MutablyBorrowed
+        );
+
+        scope.borrow_mut().restore(snapshot);
+        assert!(matches!(scope.borrow().read("f")?, Value::Function { .. }));
+        assert_eq!(scope.borrow().find_slot("n")?.borrow().state, State::Free);
+        assert!(scope.borrow().find_slot("g").is_err());
+        assert!(scope.borrow().find_slot("r").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_undoes_array_changes() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        evaluate(
+            Rc::new(parse(tokenize("let mut numbers = range(0, 2)")?)?),
+            Rc::clone(&scope),
+        )?;
+        let snapshot = scope.borrow().snapshot();
+
+        let program = parse(tokenize("push(&mut numbers, 2); set(&mut numbers, 0, 5)")?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+        assert_eq!(scope.borrow().read("numbers")?.to_string(), "[5, 1, 2]");
+
+        scope.borrow_mut().restore(snapshot);
+        assert_eq!(scope.borrow().read("numbers")?.to_string(), "[0, 1]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_declare_shares_interned_name() -> Result<()> {
+        let name = Rc::new("x".to_string());
+        let mut scope = Scope::new(None);
+        scope.declare(&name, Value::Number(1), false);
+        scope.redeclare(&name, Value::Number(2), false)?;
+
+        assert_eq!(Rc::strong_count(&name), 2);
+        assert_eq!(scope.resolve("x")?, Value::Number(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_does_not_move() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        evaluate(Rc::new(parse(tokenize("fn f(x) = x")?)?), Rc::clone(&scope))?;
+
+        let first = scope.borrow().read("f")?;
+        let second = scope.borrow_mut().resolve("f")?;
+        assert!(first.same_function(&second));
+        assert!(scope.borrow().read("f").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_copies_numbers_and_moves_tuples() -> Result<()> {
+        let mut scope = Scope::new(None);
+        scope.declare("n", Value::Number(1), false);
+        scope.declare("t", Value::Tuple(Rc::new([Value::Number(1)])), false);
+
+        assert_eq!(scope.resolve("n")?, Value::Number(1));
+        assert_eq!(scope.resolve("n")?, Value::Number(1));
+        assert_eq!(
+            scope.resolve("t")?,
+            Value::Tuple(Rc::new([Value::Number(1)]))
+        );
+        assert!(scope.resolve("t").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_assignment_copies_numbers_and_moves_strings() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        scope.borrow_mut().declare("n", Value::Number(1), false);
+        scope
+            .borrow_mut()
+            .declare("s", Value::String(Rc::from("text")), false);
+        let program = parse(tokenize("let m = n; let t = s")?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+
+        let scope = scope.borrow();
+        assert_eq!(scope.read("n")?, Value::Number(1));
+        assert_eq!(scope.read("m")?, Value::Number(1));
+        assert_eq!(scope.find_slot("s")?.borrow().value, Value::Moved);
+        assert!(matches!(
+            scope.read("s"),
+            Err(MovaError::Runtime(RuntimeError::UnableToUseBecauseMoved(_)))
+        ));
+        assert_eq!(scope.read("t")?, Value::String(Rc::from("text")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_lookup_sees_later_shadowing() -> Result<()> {
+        let root = Rc::new(RefCell::new(Scope::new(None)));
+        root.borrow_mut().declare("x", Value::Number(1), false);
+        let middle = Rc::new(RefCell::new(Scope::new(Some(Rc::clone(&root)))));
+        let leaf = Scope::new(Some(Rc::clone(&middle)));
+
+        for _ in 0..CACHE_THRESHOLD * 2 {
+            assert_eq!(leaf.read("x")?, Value::Number(1));
+        }
+
+        middle.borrow_mut().declare("x", Value::Number(2), false);
+        assert_eq!(leaf.read("x")?, Value::Number(2));
+        root.borrow_mut().declare("y", Value::Number(3), false);
+        assert_eq!(leaf.read("y")?, Value::Number(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_function_sees_redeclared_global_after_many_calls() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let program = parse(tokenize(
+            "
+            let x = 1
+            fn f() = x
+            let mut i = 0
+            let mut sum = 0
+            while i < 40 {
+                sum = sum + f()
+                i = i + 1
+            }
+            let x = 2
+            sum + f()
+            ",
+        )?)?;
+        let result = evaluate(Rc::new(program), scope)?;
+        assert_eq!(result, Some(Value::Number(42)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_indents_parent_scopes() {
+        let parent = Rc::new(RefCell::new(Scope::new(None)));
+        parent.borrow_mut().declare("x", Value::Number(1), false);
+        let mut child = Scope::new(Some(Rc::clone(&parent)));
+        child.declare("y", Value::Boolean(true), true);
+
+        assert_eq!(
+            child.dump(),
+            "y = true [borrows: 0, moved: false, mutably borrowed: false]\n  \
+             x = 1 [borrows: 0, moved: false, mutably borrowed: false]\n"
+        );
+    }
+
+    #[test]
+    fn test_scope_with_recursive_function_is_freed() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let weak = Rc::downgrade(&scope);
+        let program = parse(tokenize(
+            "
+            fn count(n) = if n < 1 { 0 } else { 1 + count(n - 1) }
+            let total = count(3)
+            ",
+        )?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+        assert_eq!(scope.borrow().read("total")?, Value::Number(3));
+
+        drop(scope);
+        assert!(weak.upgrade().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_returned_function_keeps_definition_chain_alive() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let weak = Rc::downgrade(&scope);
+        let program = parse(tokenize(
+            "
+            let base = 10;
+            fn make() = { fn add(x) = x + base; add };
+            let f = make();
+            let result = f(1)
+            ",
+        )?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+        assert_eq!(scope.borrow().read("result")?, Value::Number(11));
+
+        drop(scope);
+        assert!(weak.upgrade().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_function_assigned_out_of_block_keeps_block_alive() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let program = parse(tokenize(
+            "
+            let base = 10;
+            let mut g = 0;
+            { fn add(x) = x + base; g = add };
+            let result = g(1)
+            ",
+        )?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+        assert_eq!(scope.borrow().read("result")?, Value::Number(11));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_through_dropped_parent_fails() {
+        let parent = Rc::new(RefCell::new(Scope::new(None)));
+        parent.borrow_mut().declare("x", Value::Number(1), false);
+        let child = Scope::new(Some(Rc::clone(&parent)));
+        assert_eq!(child.read("x").unwrap(), Value::Number(1));
+
+        drop(parent);
+        assert!(matches!(
+            child.read("x"),
+            Err(MovaError::Runtime(RuntimeError::ParentScopeDropped(name))) if name == "x"
+        ));
+    }
+
+    #[test]
+    fn test_borrow_errors_name_the_blocking_borrow() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let program = parse(tokenize(
+            "let t = (1, 2); let a = &t; let b = &t; let mut n = 1; let m = &mut n",
+        )?)?;
+        evaluate(Rc::new(program), Rc::clone(&scope))?;
+
+        assert_eq!(
+            scope.borrow_mut().resolve("t").unwrap_err().to_string(),
+            "Runtime error: Unable to move 't' because it is borrowed (active borrows: 2)"
+        );
+        assert_eq!(
+            scope.borrow_mut().resolve("n").unwrap_err().to_string(),
+            "Runtime error: Unable to use 'n' because it is mutably borrowed"
+        );
+        assert_eq!(
+            scope.borrow().read("n").unwrap_err().to_string(),
+            "Runtime error: Unable to use 'n' because it is mutably borrowed"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cannot_shadow_borrowed_variable() {
+        let result = run("let x = 1; let r = &x; let x = 2");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Runtime error: Cannot shadow 'x' while it is borrowed"
+        );
+        let result = run("let mut x = 1; let r = &mut x; fn x() = 2");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_can_shadow_unborrowed_variable() -> Result<()> {
+        assert_eq!(run("let x = 1; let x = x + 1; x")?, Some(Value::Number(2)));
+        assert_eq!(
+            run("let x = 1; let r = &x; drop(r); let x = 2; x")?,
+            Some(Value::Number(2))
+        );
+        // A block's own variable does not replace the borrowed one outside it
+        assert_eq!(
+            run("let x = 1; let r = &x; { let x = 2; x }")?,
+            Some(Value::Number(2))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_can_shadow_variable_with_its_own_reference() -> Result<()> {
+        assert_eq!(
+            run("let x = 1; let x = &x; *x + 1")?,
+            Some(Value::Number(2))
+        );
+        assert!(run("let mut x = 1; let x = &mut x; *x = 5").is_ok());
+        assert!(run("let x = 1; let y = 2; let r = &x; let x = &y").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_with_depth_reports_shadowing() -> Result<()> {
+        let root = Rc::new(RefCell::new(Scope::new(None)));
+        root.borrow_mut().declare("x", Value::Number(1), false);
+        root.borrow_mut().declare("y", Value::Number(2), false);
+        let middle = Rc::new(RefCell::new(Scope::new(Some(Rc::clone(&root)))));
+        let mut child = Scope::new(Some(Rc::clone(&middle)));
+        child.declare("x", Value::Number(3), false);
+
+        assert_eq!(child.resolve_with_depth("x")?, (Value::Number(3), 0));
+        assert_eq!(child.resolve_shadowed("x", 1)?, (Value::Number(1), 2));
+        assert_eq!(child.resolve_with_depth("y")?, (Value::Number(2), 2));
+        assert!(matches!(
+            child.resolve_shadowed("x", 2),
+            Err(MovaError::Runtime(RuntimeError::UnableToResolve(_)))
+        ));
+        Ok(())
+    }
+}
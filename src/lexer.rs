@@ -1,3 +1,5 @@
+use std::{collections::HashMap, fmt, iter::Peekable, str::CharIndices};
+
 use crate::error::{MovaError, Position, Result};
 
 #[derive(Clone, Debug, PartialEq)]
@@ -6,90 +8,569 @@ pub enum Token {
     Identifier(String),
     Number(String),
     Boolean(bool),
-    Operator(String),
+    Operator(Operator),
     Assignment,
     SpecialCharacter(char),
+    Comment(String),
+    /// A `///` comment documenting the declaration after it, without the slashes
+    DocComment(String),
+    /// End of a line, only emitted when newlines are significant
+    Newline,
+    /// A line indented deeper than the one before, only emitted when indentation is significant
+    Indent,
+    /// Closes the innermost `Indent` once a line returns to an enclosing indentation
+    Dedent,
+    /// End of input, never emitted by the lexer but appended by the parser
+    Eof,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operator {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Dot,
+    Amp,
+    AmpAmp,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+    Less,
+    Greater,
+    EqualEqual,
+    NotEqual,
+}
+
+impl Operator {
+    /// How the operator is spelled in source
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Star => "*",
+            Operator::Slash => "/",
+            Operator::Percent => "%",
+            Operator::LParen => "(",
+            Operator::RParen => ")",
+            Operator::LBracket => "[",
+            Operator::RBracket => "]",
+            Operator::Dot => ".",
+            Operator::Amp => "&",
+            Operator::AmpAmp => "&&",
+            Operator::Pipe => "|",
+            Operator::Caret => "^",
+            Operator::Tilde => "~",
+            Operator::ShiftLeft => "<<",
+            Operator::ShiftRight => ">>",
+            Operator::Less => "<",
+            Operator::Greater => ">",
+            Operator::EqualEqual => "==",
+            Operator::NotEqual => "!=",
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Describes a token the way error messages refer to it
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Keyword(k) => write!(f, "keyword '{k}'"),
+            Token::Identifier(i) => write!(f, "identifier '{i}'"),
+            Token::Number(n) => write!(f, "number {n}"),
+            Token::Boolean(b) => write!(f, "'{b}'"),
+            Token::Operator(o) => write!(f, "'{o}'"),
+            Token::Assignment => f.write_str("'='"),
+            Token::SpecialCharacter(c) => write!(f, "'{c}'"),
+            Token::Comment(_) => f.write_str("comment"),
+            Token::DocComment(_) => f.write_str("doc comment"),
+            Token::Newline => f.write_str("end of line"),
+            Token::Indent => f.write_str("indentation"),
+            Token::Dedent => f.write_str("end of indentation"),
+            Token::Eof => f.write_str("end of input"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    /// 1-based column of the first character within its line
+    pub column: usize,
+    /// 1-based column right after the last character, within the line where the span ends
+    pub end_column: usize,
+}
+
+const KEYWORDS: [&str; 8] = ["let", "mut", "fn", "if", "else", "while", "do", "return"];
+
+/// How each keyword is spelled in source, which lets dialects rename them
+#[derive(Clone, Debug)]
+pub struct Keywords {
+    /// Maps a spelling to the keyword it stands for
+    spellings: HashMap<String, String>,
+}
+
+impl Default for Keywords {
+    fn default() -> Self {
+        Self {
+            spellings: KEYWORDS
+                .iter()
+                .map(|k| (k.to_string(), k.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Keywords {
+    /// Spells `keyword` as `spelling` instead of its current spelling
+    pub fn with_spelling(mut self, keyword: &str, spelling: &str) -> Self {
+        self.spellings.retain(|_, k| k != keyword);
+        self.spellings.insert(spelling.into(), keyword.into());
+        self
+    }
+
+    /// The keyword spelled by `word`, if any
+    pub fn get(&self, word: &str) -> Option<&str> {
+        self.spellings.get(word).map(String::as_str)
+    }
+}
+
+/// Options controlling how source text is split into tokens
+#[derive(Clone, Debug)]
+pub struct LexerOptions {
+    /// Prefixes that start a comment running to the end of the line
+    pub comment_prefixes: Vec<String>,
+    pub preserve_comments: bool,
+    pub keywords: Keywords,
+    /// Emits `Token::Newline` at line ends so that they can terminate statements
+    pub significant_newlines: bool,
+    /// Emits `Token::Indent` and `Token::Dedent` as lines are indented, so that blocks can be
+    /// written without braces
+    pub significant_indentation: bool,
+}
+
+impl Default for LexerOptions {
+    fn default() -> Self {
+        Self {
+            comment_prefixes: vec!["//".into()],
+            preserve_comments: false,
+            keywords: Keywords::default(),
+            significant_newlines: false,
+            significant_indentation: false,
+        }
+    }
 }
 
 pub fn tokenize(input: &str) -> Result<Vec<Token>> {
+    tokenize_with(input, LexerOptions::default())
+}
+
+pub fn tokenize_with(input: &str, options: LexerOptions) -> Result<Vec<Token>> {
+    Lexer::new(input, options)
+        .map(|result| result.map(|(token, _)| token))
+        .collect()
+}
+
+/// Tokenizes the whole input, skipping unexpected characters and reporting each of them
+pub fn tokenize_all(input: &str) -> (Vec<Token>, Vec<MovaError>) {
     let mut tokens = Vec::new();
-    let mut input = input.char_indices().peekable();
-    let mut line = 1;
+    let mut errors = Vec::new();
+
+    for result in Lexer::new(input, LexerOptions::default()).recovering() {
+        match result {
+            Ok((token, _)) => tokens.push(token),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    (tokens, errors)
+}
+
+/// Lazily tokenizes the input, stopping after the first error
+pub fn tokens(input: &str) -> impl Iterator<Item = Result<Token>> + '_ {
+    Lexer::new(input, LexerOptions::default()).map(|result| result.map(|(token, _)| token))
+}
+
+/// Tokenizes the input and pairs every token with its byte range in the source,
+/// optionally keeping comments as `Token::Comment` and `Token::DocComment`
+pub fn tokenize_with_spans(source: &str, preserve_comments: bool) -> Result<Vec<(Token, Span)>> {
+    let options = LexerOptions {
+        preserve_comments,
+        ..LexerOptions::default()
+    };
+    Lexer::new(source, options).collect()
+}
+
+pub struct Lexer<'a> {
+    source: &'a str,
+    input: Peekable<CharIndices<'a>>,
+    line: usize,
+    /// Byte offset at which the current line begins
+    line_start: usize,
+    /// The last byte offset whose column was counted, and that column, so that counting
+    /// resumes from there instead of from the start of the line
+    counted: (usize, usize),
+    options: LexerOptions,
+    is_recovering: bool,
+    is_finished: bool,
+    /// Whether nothing but newlines was emitted since the last `Token::Newline`
+    is_line_empty: bool,
+    /// Whether the next character begins a line whose indentation hasn't been measured yet
+    is_line_start: bool,
+    /// Indentation of every enclosing indented line, innermost last
+    indentation: Vec<usize>,
+    /// `Token::Dedent`s still to be emitted for the current line
+    pending_dedents: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str, options: LexerOptions) -> Self {
+        Self {
+            source,
+            input: source.char_indices().peekable(),
+            line: 1,
+            line_start: 0,
+            counted: (0, 1),
+            options,
+            is_recovering: false,
+            is_finished: false,
+            is_line_empty: true,
+            is_line_start: true,
+            indentation: vec![0],
+            pending_dedents: 0,
+        }
+    }
+
+    /// Keeps lexing after an unexpected character instead of stopping at the first error
+    pub fn recovering(mut self) -> Self {
+        self.is_recovering = true;
+        self
+    }
+
+    fn span(&mut self, start: usize, end: usize) -> Span {
+        Span {
+            start,
+            end,
+            line: self.line,
+            column: self.column(start),
+            end_column: self.column(end),
+        }
+    }
+
+    /// 1-based column of the byte offset `offset` on the current line
+    fn column(&mut self, offset: usize) -> usize {
+        let (from, column) = match self.counted {
+            (from, column) if from <= offset => (from, column),
+            _ => (self.line_start, 1),
+        };
+        let column = column + self.source[from..offset].chars().count();
+        self.counted = (offset, column);
+        column
+    }
+
+    /// Moves to the line that begins right after the newline at byte offset `newline`
+    fn next_line(&mut self, newline: usize) {
+        self.line += 1;
+        self.line_start = newline + 1;
+        self.counted = (self.line_start, 1);
+    }
+
+    /// Consumes a comment starting at `start` if one of the configured prefixes matches
+    fn comment(&mut self, start: usize) -> Option<String> {
+        let rest = &self.source[start..];
+        let prefix = self
+            .options
+            .comment_prefixes
+            .iter()
+            .find(|p| !p.is_empty() && rest.starts_with(p.as_str()))?;
 
-    while let Some((i, c)) = input.next() {
-        if c.is_whitespace() {
-            if c == '\n' {
-                line += 1;
+        for _ in prefix.chars().skip(1) {
+            self.input.next();
+        }
+        let mut comment = String::new();
+        while let Some((_, n)) = self.input.next_if(|(_, n)| *n != '\n') {
+            comment.push(n);
+        }
+        Some(comment)
+    }
+
+    /// Consumes the leading whitespace of a line and compares its width with the enclosing
+    /// lines, returning the `Token::Indent` or first `Token::Dedent` it produces, if any
+    fn indentation(&mut self) -> Option<Result<(Token, Span)>> {
+        self.is_line_start = false;
+        let mut width = 0;
+        while self
+            .input
+            .next_if(|(_, c)| *c == ' ' || *c == '\t')
+            .is_some()
+        {
+            width += 1;
+        }
+
+        let (start, _) = *self.input.peek()?;
+        let rest = &self.source[start..];
+        // Blank and comment-only lines don't open or close blocks
+        let is_blank = rest.starts_with(['\n', '\r'])
+            || rest.starts_with("/*")
+            || self
+                .options
+                .comment_prefixes
+                .iter()
+                .any(|p| !p.is_empty() && rest.starts_with(p.as_str()));
+        let current = *self.indentation.last()?;
+        if is_blank || width == current {
+            return None;
+        }
+
+        let span = self.span(start, start);
+        if width > current {
+            self.indentation.push(width);
+            return Some(Ok((Token::Indent, span)));
+        }
+
+        while self.indentation.last().is_some_and(|&level| level > width) {
+            self.indentation.pop();
+            self.pending_dedents += 1;
+        }
+        if self.indentation.last() != Some(&width) {
+            self.is_finished = true;
+            return Some(Err(MovaError::InconsistentIndentation {
+                position: Position {
+                    line: self.line,
+                    character: self.column(start),
+                },
+            }));
+        }
+        self.pending_dedents -= 1;
+        Some(Ok((Token::Dedent, span)))
+    }
+
+    /// Consumes the rest of a `/* ... */` comment after its opening `/`,
+    /// or returns `None` if the input ends before it is closed
+    fn block_comment(&mut self) -> Option<String> {
+        self.input.next();
+        let mut comment = String::new();
+        loop {
+            let (i, c) = self.input.next()?;
+            match c {
+                '*' if self.input.next_if(|(_, n)| *n == '/').is_some() => return Some(comment),
+                '\n' => self.next_line(i),
+                _ => {}
             }
-            continue;
+            comment.push(c);
         }
+    }
+}
 
-        match c {
-            '/' => {
-                if let Some((_, '/')) = input.peek() {
-                    input.next();
-                    while let Some((_, n)) = input.next() {
-                        if n == '\n' {
-                            line += 1;
-                            break;
-                        }
-                    }
-                } else {
-                    tokens.push(Token::Operator(c.into()));
+impl Iterator for Lexer<'_> {
+    type Item = Result<(Token, Span)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+
+        if self.pending_dedents > 0 {
+            self.pending_dedents -= 1;
+            let end = self
+                .input
+                .peek()
+                .map_or(self.source.len(), |(next, _)| *next);
+            return Some(Ok((Token::Dedent, self.span(end, end))));
+        }
+
+        loop {
+            if self.options.significant_indentation
+                && self.is_line_start
+                && let Some(token) = self.indentation()
+            {
+                return Some(token);
+            }
+
+            let Some((i, c)) = self.input.next() else {
+                // Close the blocks still open at the end of the input
+                if self.indentation.len() > 1 {
+                    self.indentation.pop();
+                    let end = self.source.len();
+                    return Some(Ok((Token::Dedent, self.span(end, end))));
+                }
+                return None;
+            };
+
+            if c.is_whitespace() {
+                if c != '\n' {
+                    continue;
+                }
+                // The newline belongs to the line it ends
+                let span = self.span(i, i + 1);
+                self.next_line(i);
+                self.is_line_start = true;
+                // Consecutive newlines collapse into one
+                if !self.options.significant_newlines || self.is_line_empty {
+                    continue;
                 }
+                self.is_line_empty = true;
+                return Some(Ok((Token::Newline, span)));
             }
-            'a'..='z' | 'A'..='Z' | '_' => {
-                let mut value = String::from(c);
-                while let Some((_, l)) = input.peek() {
-                    match l {
-                        'a'..='z' | 'A'..='Z' | '_' | '0'..='9' => {
-                            let (_, next) = input.next().unwrap();
-                            value += &next.to_string();
-                        }
-                        _ => break,
-                    }
+
+            if c == '/' && matches!(self.input.peek(), Some((_, '*'))) {
+                let line = self.line;
+                let column = self.column(i);
+                let Some(comment) = self.block_comment() else {
+                    self.is_finished = true;
+                    return Some(Err(MovaError::UnterminatedComment {
+                        position: Position {
+                            line,
+                            character: column,
+                        },
+                    }));
+                };
+                if !self.options.preserve_comments {
+                    continue;
+                }
+                self.is_line_empty = false;
+                let end = self
+                    .input
+                    .peek()
+                    .map_or(self.source.len(), |(next, _)| *next);
+                return Some(Ok((
+                    Token::Comment(comment),
+                    Span {
+                        start: i,
+                        end,
+                        line,
+                        column,
+                        end_column: self.column(end),
+                    },
+                )));
+            }
+
+            if let Some(comment) = self.comment(i) {
+                if !self.options.preserve_comments {
+                    continue;
                 }
-                let token = match value.as_str() {
-                    "let" | "mut" | "fn" | "if" | "else" | "while" => Token::Keyword(value),
-                    "true" => Token::Boolean(true),
-                    "false" => Token::Boolean(false),
-                    _ => Token::Identifier(value),
+                self.is_line_empty = false;
+                let end = self
+                    .input
+                    .peek()
+                    .map_or(self.source.len(), |(next, _)| *next);
+                // As in Rust, four or more slashes make an ordinary comment again
+                let token = match comment.strip_prefix('/') {
+                    Some(doc) if self.source[i..].starts_with("///") && !doc.starts_with('/') => {
+                        Token::DocComment(doc.strip_prefix(' ').unwrap_or(doc).to_string())
+                    }
+                    _ => Token::Comment(comment),
                 };
-                tokens.push(token);
+                return Some(Ok((token, self.span(i, end))));
             }
-            '0'..='9' => {
-                let mut value = String::from(c);
-                while let Some((_, l)) = input.peek() {
-                    match l {
-                        '0'..='9' => {
-                            let (_, next) = input.next().unwrap();
-                            value += &next.to_string()
+
+            let token = match c {
+                c if c.is_alphabetic() || c == '_' => {
+                    let mut value = String::from(c);
+                    while let Some((_, next)) = self
+                        .input
+                        .next_if(|(_, l)| l.is_alphanumeric() || *l == '_')
+                    {
+                        value.push(next);
+                    }
+                    if let Some(keyword) = self.options.keywords.get(&value) {
+                        Token::Keyword(keyword.into())
+                    } else {
+                        match value.as_str() {
+                            "true" => Token::Boolean(true),
+                            "false" => Token::Boolean(false),
+                            _ => Token::Identifier(value),
                         }
-                        _ => break,
                     }
                 }
-                tokens.push(Token::Number(value));
-            }
-            '+' | '-' | '*' | '(' | ')' | '&' | '<' | '>' => tokens.push(Token::Operator(c.into())),
-            '=' => {
-                if let Some((_, '=')) = input.peek() {
-                    input.next();
-                    tokens.push(Token::Operator("==".into()));
-                } else {
-                    tokens.push(Token::Assignment);
+                '0'..='9' => {
+                    let mut value = String::from(c);
+                    // Radix prefixes like `0x` allow letters as digits, which the parser validates
+                    let is_prefixed =
+                        c == '0' && matches!(self.input.peek(), Some((_, 'x' | 'o' | 'b')));
+                    while let Some((_, l)) = self.input.peek() {
+                        match l {
+                            'a'..='z' | 'A'..='Z' if is_prefixed => {
+                                let (_, next) = self.input.next().unwrap();
+                                value.push(next)
+                            }
+                            '0'..='9' => {
+                                let (_, next) = self.input.next().unwrap();
+                                value.push(next)
+                            }
+                            _ => break,
+                        }
+                    }
+                    Token::Number(value)
                 }
-            }
-            '{' | '}' | ',' | ';' => tokens.push(Token::SpecialCharacter(c)),
-            _ => {
-                return Err(MovaError::Lexer {
-                    character: c,
-                    position: Position { line, character: i },
-                });
-            }
+                '+' => Token::Operator(Operator::Plus),
+                '-' => Token::Operator(Operator::Minus),
+                '*' => Token::Operator(Operator::Star),
+                '/' => Token::Operator(Operator::Slash),
+                '%' => Token::Operator(Operator::Percent),
+                '(' => Token::Operator(Operator::LParen),
+                ')' => Token::Operator(Operator::RParen),
+                '[' => Token::Operator(Operator::LBracket),
+                ']' => Token::Operator(Operator::RBracket),
+                '.' => Token::Operator(Operator::Dot),
+                '|' => Token::Operator(Operator::Pipe),
+                '^' => Token::Operator(Operator::Caret),
+                '~' => Token::Operator(Operator::Tilde),
+                '<' if self.input.next_if(|(_, n)| *n == '<').is_some() => {
+                    Token::Operator(Operator::ShiftLeft)
+                }
+                '<' => Token::Operator(Operator::Less),
+                '>' if self.input.next_if(|(_, n)| *n == '>').is_some() => {
+                    Token::Operator(Operator::ShiftRight)
+                }
+                '>' => Token::Operator(Operator::Greater),
+                '&' if self.input.next_if(|(_, n)| *n == '&').is_some() => {
+                    Token::Operator(Operator::AmpAmp)
+                }
+                '&' => Token::Operator(Operator::Amp),
+                '!' if self.input.next_if(|(_, n)| *n == '=').is_some() => {
+                    Token::Operator(Operator::NotEqual)
+                }
+                '=' if self.input.next_if(|(_, n)| *n == '=').is_some() => {
+                    Token::Operator(Operator::EqualEqual)
+                }
+                '=' => Token::Assignment,
+                '{' | '}' | ',' | ';' => Token::SpecialCharacter(c),
+                _ => {
+                    self.is_finished = !self.is_recovering;
+                    return Some(Err(MovaError::Lexer {
+                        character: c,
+                        position: Position {
+                            line: self.line,
+                            character: self.column(i),
+                        },
+                    }));
+                }
+            };
+
+            self.is_line_empty = false;
+            let end = self
+                .input
+                .peek()
+                .map_or(self.source.len(), |(next, _)| *next);
+            return Some(Ok((token, self.span(i, end))));
         }
     }
-
-    Ok(tokens)
 }
 
 #[cfg(test)]
@@ -119,13 +600,25 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn it_tokenizes_prefixed_number() -> Result<()> {
+        let numbers = vec![
+            Token::Number("0xff".into()),
+            Token::Number("0b1010".into()),
+            Token::Number("0o17".into()),
+            Token::Number("0".into()),
+        ];
+        assert_eq!(tokenize("0xff 0b1010 0o17 0")?, numbers);
+        Ok(())
+    }
+
     #[test]
     fn it_tokenizes_operator() -> Result<()> {
         let operators = vec![
-            Token::Operator('+'.into()),
-            Token::Operator('-'.into()),
-            Token::Operator('-'.into()),
-            Token::Operator('/'.into()),
+            Token::Operator(Operator::Plus),
+            Token::Operator(Operator::Minus),
+            Token::Operator(Operator::Minus),
+            Token::Operator(Operator::Slash),
         ];
         assert_eq!(tokenize("+-- /")?, operators);
         Ok(())
@@ -134,9 +627,9 @@ mod tests {
     #[test]
     fn it_tokenizes_special_character() -> Result<()> {
         let special_characters = vec![
-            Token::SpecialCharacter('{'.into()),
-            Token::SpecialCharacter('}'.into()),
-            Token::SpecialCharacter('}'.into()),
+            Token::SpecialCharacter('{'),
+            Token::SpecialCharacter('}'),
+            Token::SpecialCharacter('}'),
         ];
         assert_eq!(tokenize("{}}")?, special_characters);
         Ok(())
@@ -156,4 +649,397 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn it_skips_block_comment() -> Result<()> {
+        assert_eq!(
+            tokenize("1 /* two */ 3 /**/")?,
+            vec![Token::Number("1".into()), Token::Number("3".into())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_positions_token_after_line_comment() -> Result<()> {
+        let input = "x // a rather long comment about x\n  y";
+        let tokens = tokenize_with_spans(input, false)?;
+        let (token, span) = &tokens[1];
+        assert_eq!(token, &Token::Identifier("y".into()));
+        assert_eq!((span.line, span.column), (2, 3));
+        assert_eq!(&input[span.start..span.end], "y");
+        Ok(())
+    }
+
+    #[test]
+    fn it_positions_token_after_block_comment() -> Result<()> {
+        let input = "x /* multi\nline\n */ y\nz";
+        let tokens = tokenize_with_spans(input, false)?;
+        let positions: Vec<(usize, usize)> = tokens
+            .iter()
+            .map(|(_, span)| (span.line, span.column))
+            .collect();
+        assert_eq!(positions, vec![(1, 1), (3, 5), (4, 1)]);
+
+        let preserved = tokenize_with_spans(input, true)?;
+        let (comment, span) = &preserved[1];
+        assert_eq!(comment, &Token::Comment(" multi\nline\n ".into()));
+        assert_eq!((span.line, span.column), (1, 3));
+        assert_eq!(&input[span.start..span.end], "/* multi\nline\n */");
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_unterminated_block_comment() {
+        match tokenize("1\n2 /* never closed\n3") {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Lexer error at 2:3: Unterminated block comment"
+            ),
+            Ok(tokens) => panic!("Expected an error but got {tokens:?}"),
+        }
+    }
+
+    #[test]
+    fn it_reconstructs_source_from_spans() -> Result<()> {
+        let input = "let x = 10 // ten\nfn add(a, b) = {\n    a + b\n}\nadd(x, 2)";
+        let tokens = tokenize_with_spans(input, true)?;
+
+        let mut reconstructed = String::new();
+        let mut previous_end = 0;
+        for (_, span) in &tokens {
+            let gap = &input[previous_end..span.start];
+            assert!(gap.trim().is_empty(), "Unexpected gap: {gap:?}");
+            reconstructed += gap;
+            reconstructed += &input[span.start..span.end];
+            previous_end = span.end;
+        }
+        reconstructed += &input[previous_end..];
+
+        assert_eq!(reconstructed, input);
+        assert!(tokens.contains(&(
+            Token::Comment(" ten".into()),
+            Span {
+                start: 11,
+                end: 17,
+                line: 1,
+                column: 12,
+                end_column: 18
+            }
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn it_preserves_doc_comments() -> Result<()> {
+        let tokens: Vec<Token> =
+            tokenize_with_spans("/// Adds\n// plain\n////\n///// rule\nfn", true)?
+                .into_iter()
+                .map(|(token, _)| token)
+                .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::DocComment("Adds".into()),
+                Token::Comment(" plain".into()),
+                Token::Comment("//".into()),
+                Token::Comment("/// rule".into()),
+                Token::Keyword("fn".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_discards_comments_by_default() -> Result<()> {
+        let tokens = tokenize_with_spans("1 // one", false)?;
+        assert_eq!(
+            tokens,
+            vec![(
+                Token::Number("1".into()),
+                Span {
+                    start: 0,
+                    end: 1,
+                    line: 1,
+                    column: 1,
+                    end_column: 2
+                }
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_yields_tokens_lazily() -> Result<()> {
+        let input = "let x = 1\nfn f(a) = { a * 2 }\nf(x)";
+        let lazy = tokens(input).collect::<Result<Vec<Token>>>()?;
+        assert_eq!(lazy, tokenize(input)?);
+
+        let first_two = tokens(input).take(2).collect::<Result<Vec<Token>>>()?;
+        assert_eq!(
+            first_two,
+            vec![Token::Keyword("let".into()), Token::Identifier("x".into())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_yields_error_at_position() {
+        let mut iterator = tokens("1 +\n2 @ 3");
+        assert_eq!(iterator.next().unwrap().unwrap(), Token::Number("1".into()));
+        assert_eq!(
+            iterator.next().unwrap().unwrap(),
+            Token::Operator(Operator::Plus)
+        );
+        assert_eq!(iterator.next().unwrap().unwrap(), Token::Number("2".into()));
+        match iterator.next() {
+            Some(Err(MovaError::Lexer {
+                character,
+                position,
+            })) => {
+                assert_eq!(character, '@');
+                assert_eq!(
+                    position,
+                    Position {
+                        line: 2,
+                        character: 3
+                    }
+                );
+            }
+            t => panic!("Expected a lexer error but got {t:?}"),
+        }
+        assert!(iterator.next().is_none());
+    }
+
+    #[test]
+    fn it_skips_hash_comments_when_enabled() -> Result<()> {
+        let options = LexerOptions {
+            comment_prefixes: vec!["//".into(), "#".into()],
+            ..LexerOptions::default()
+        };
+        let input = "# header\nlet x = 1 # one\n// two\nx @";
+        match tokenize_with(input, options) {
+            Err(MovaError::Lexer {
+                character,
+                position,
+            }) => {
+                assert_eq!(character, '@');
+                assert_eq!(position.line, 4);
+            }
+            t => panic!("Expected a lexer error but got {t:?}"),
+        }
+
+        let options = LexerOptions {
+            comment_prefixes: vec!["#".into()],
+            ..LexerOptions::default()
+        };
+        assert_eq!(
+            tokenize_with("1 # one\n2", options)?,
+            vec![Token::Number("1".into()), Token::Number("2".into())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_hash_by_default() {
+        assert!(matches!(
+            tokenize("# comment"),
+            Err(MovaError::Lexer { character: '#', .. })
+        ));
+    }
+
+    #[test]
+    fn it_counts_columns_in_characters() -> Result<()> {
+        let tokens = tokenize_with_spans("let ціна = 1\n  ціна", false)?;
+        let columns: Vec<(usize, usize, usize)> = tokens
+            .iter()
+            .map(|(_, span)| (span.line, span.column, span.end_column))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![(1, 1, 4), (1, 5, 9), (1, 10, 11), (1, 12, 13), (2, 3, 7)]
+        );
+
+        let (_, errors) = tokenize_all("ціна @");
+        assert_eq!(
+            errors[0].to_string(),
+            "Lexer error at 1:6: Unexpected character: '@'"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_every_unexpected_character() {
+        let (tokens, errors) = tokenize_all("let x = 1 @\nx $ 2");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword("let".into()),
+                Token::Identifier("x".into()),
+                Token::Assignment,
+                Token::Number("1".into()),
+                Token::Identifier("x".into()),
+                Token::Number("2".into()),
+            ]
+        );
+
+        let positions: Vec<(char, Position)> = errors
+            .into_iter()
+            .map(|e| match e {
+                MovaError::Lexer {
+                    character,
+                    position,
+                } => (character, position),
+                e => panic!("Expected a lexer error but got {e:?}"),
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (
+                    '@',
+                    Position {
+                        line: 1,
+                        character: 11
+                    }
+                ),
+                (
+                    '$',
+                    Position {
+                        line: 2,
+                        character: 3
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_tokenizes_renamed_keyword() -> Result<()> {
+        let options = LexerOptions {
+            keywords: Keywords::default().with_spelling("let", "нехай"),
+            ..LexerOptions::default()
+        };
+        assert_eq!(
+            tokenize_with("нехай x = 1\nlet", options)?,
+            vec![
+                Token::Keyword("let".into()),
+                Token::Identifier("x".into()),
+                Token::Assignment,
+                Token::Number("1".into()),
+                Token::Identifier("let".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_tokenizes_bitwise_operators() -> Result<()> {
+        let operators: Vec<Token> = [
+            Operator::Tilde,
+            Operator::ShiftLeft,
+            Operator::ShiftRight,
+            Operator::Less,
+            Operator::Pipe,
+            Operator::Caret,
+            Operator::Amp,
+        ]
+        .into_iter()
+        .map(Token::Operator)
+        .collect();
+        assert_eq!(tokenize("~ << >> < | ^ &")?, operators);
+        Ok(())
+    }
+
+    #[test]
+    fn it_tokenizes_every_operator_from_its_spelling() -> Result<()> {
+        use Operator::*;
+        let operators = [
+            Plus, Minus, Star, Slash, Percent, LParen, RParen, Dot, Amp, AmpAmp, Pipe, Caret,
+            Tilde, ShiftLeft, ShiftRight, Less, Greater, EqualEqual, NotEqual,
+        ];
+        for operator in operators {
+            assert_eq!(
+                tokenize(operator.as_str())?,
+                vec![Token::Operator(operator)]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_emits_collapsed_newlines_when_significant() -> Result<()> {
+        let options = LexerOptions {
+            significant_newlines: true,
+            ..LexerOptions::default()
+        };
+        assert_eq!(
+            tokenize_with("\na\n\n  // note\n\nb\n", options)?,
+            vec![
+                Token::Identifier("a".into()),
+                Token::Newline,
+                Token::Identifier("b".into()),
+                Token::Newline,
+            ]
+        );
+        assert_eq!(
+            tokenize("a\nb")?,
+            vec![Token::Identifier("a".into()), Token::Identifier("b".into())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_emits_indents_and_dedents_when_significant() -> Result<()> {
+        let options = LexerOptions {
+            significant_indentation: true,
+            ..LexerOptions::default()
+        };
+        let input = "while a\n    b\n\n    // note\n    if c\n        d\ne";
+        let identifier = |name: &str| Token::Identifier(name.into());
+        assert_eq!(
+            tokenize_with(input, options.clone())?,
+            vec![
+                Token::Keyword("while".into()),
+                identifier("a"),
+                Token::Indent,
+                identifier("b"),
+                Token::Keyword("if".into()),
+                identifier("c"),
+                Token::Indent,
+                identifier("d"),
+                Token::Dedent,
+                Token::Dedent,
+                identifier("e"),
+            ]
+        );
+
+        // Blocks still open at the end of the input are closed
+        assert_eq!(
+            tokenize_with("a\n  b", options.clone())?,
+            vec![
+                identifier("a"),
+                Token::Indent,
+                identifier("b"),
+                Token::Dedent
+            ]
+        );
+
+        match tokenize_with("a\n    b\n  c", options) {
+            Err(MovaError::InconsistentIndentation { position }) => assert_eq!(position.line, 3),
+            t => panic!("Expected an indentation error but got {t:?}"),
+        }
+        assert_eq!(tokenize("a\n  b")?, vec![identifier("a"), identifier("b")]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_tokenizes_very_long_identifier_and_number() -> Result<()> {
+        let identifier = "a".repeat(100_000);
+        let number = "9".repeat(100_000);
+        assert_eq!(
+            tokenize(&format!("{identifier} {number}"))?,
+            vec![Token::Identifier(identifier), Token::Number(number)]
+        );
+        Ok(())
+    }
 }
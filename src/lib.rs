@@ -1,5 +1,7 @@
+pub mod compiler;
 pub mod error;
 pub mod interpreter;
 pub mod lexer;
 pub mod parser;
 pub mod runner;
+pub mod vm;
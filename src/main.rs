@@ -1,42 +1,281 @@
 use std::{env, fs};
 
-use mova::{interpreter::Value, runner::run};
+use mova::{
+    error::{MovaError, Position},
+    interpreter::Value,
+    lexer::{tokenize_all, tokenize_with_spans},
+    parser::{ParserOptions, parse_all},
+    runner::run,
+};
 
 fn main() {
     ctrlc::set_handler(move || std::process::exit(0)).expect("Error setting Ctrl-C handler");
 
-    let args: Vec<String> = env::args().collect();
-    let paths = &args[1..];
+    let args: Vec<String> = env::args().skip(1).collect();
+    let fail_fast = args.iter().any(|arg| arg == "--fail-fast");
+    let diagnostics_json = args.iter().any(|arg| arg == "--diagnostics-json");
+    let exit_from_result = args.iter().any(|arg| arg == "--exit-from-result");
+    let paths: Vec<&str> = args
+        .iter()
+        .filter(|arg| {
+            !matches!(
+                arg.as_str(),
+                "--fail-fast" | "--diagnostics-json" | "--exit-from-result"
+            )
+        })
+        .map(String::as_str)
+        .collect();
 
-    paths.into_iter().for_each(|path| {
-        let input = match fs::read_to_string(path) {
-            Ok(content) => content,
+    if diagnostics_json {
+        let (json, has_errors) = diagnose_files(&paths);
+        println!("{json}");
+        std::process::exit(i32::from(has_errors));
+    }
+
+    let (failed, result) = run_files(&paths, fail_fast);
+    if !failed.is_empty() {
+        eprintln!("Failed: {}", failed.join(", "));
+        std::process::exit(1);
+    }
+    if exit_from_result {
+        std::process::exit(exit_code(result.as_ref()));
+    }
+}
+
+/// Runs every file in order and returns the paths of those that failed,
+/// along with the value the last file evaluated to
+fn run_files<'a>(paths: &[&'a str], fail_fast: bool) -> (Vec<&'a str>, Option<Value>) {
+    let mut failed = Vec::new();
+    let mut last = None;
+
+    for path in paths {
+        match run_file(path) {
+            Ok(result) => last = result,
             Err(e) => {
-                eprintln!("Error reading file {path}: {e}");
-                std::process::exit(1);
-            }
-        };
-
-        match run(&input) {
-            Ok(result) => {
-                if let Some(value) = result {
-                    match value {
-                        Value::Reference(r) => match r.read() {
-                            Ok(guard) => {
-                                println!("{:?}", guard.value);
-                            }
-                            Err(e) => {
-                                println!("{e}");
-                            }
-                        },
-                        _ => println!("{value:?}"),
-                    }
+                eprintln!("{e}");
+                failed.push(*path);
+                last = None;
+                if fail_fast {
+                    break;
                 }
             }
-            Err(e) => {
-                eprintln!("{e}");
-                std::process::exit(1);
+        }
+    }
+
+    (failed, last)
+}
+
+/// Exit code for `--exit-from-result`: a number is used directly, `false` is a failure and
+/// anything else a success
+fn exit_code(result: Option<&Value>) -> i32 {
+    match result {
+        Some(Value::Number(n)) => n.rem_euclid(256),
+        Some(Value::Boolean(false)) => 1,
+        _ => 0,
+    }
+}
+
+fn run_file(path: &str) -> Result<Option<Value>, String> {
+    let input = fs::read_to_string(path).map_err(|e| format!("Error reading file {path}: {e}"))?;
+
+    match run(&input) {
+        Ok(result) => {
+            if let Some(value) = &result {
+                match value {
+                    Value::Reference(r) => match r.read() {
+                        Ok(guard) => {
+                            println!("{:?}", guard.value);
+                        }
+                        Err(e) => {
+                            println!("{e}");
+                        }
+                    },
+                    _ => println!("{value:?}"),
+                }
             }
+            Ok(result)
         }
-    });
+        Err(e) => Err(format!("{path}: {e}")),
+    }
+}
+
+/// Checks every file and renders the problems found as a JSON array for editors,
+/// also reporting whether there were any
+fn diagnose_files(paths: &[&str]) -> (String, bool) {
+    let mut diagnostics = Vec::new();
+
+    for path in paths {
+        match fs::read_to_string(path) {
+            Ok(input) => diagnostics.extend(
+                collect_diagnostics(&input)
+                    .iter()
+                    .map(|error| diagnostic_json(path, &error.to_string(), error.position())),
+            ),
+            Err(e) => diagnostics.push(diagnostic_json(
+                path,
+                &format!("Error reading file: {e}"),
+                None,
+            )),
+        }
+    }
+
+    (
+        format!("[{}]", diagnostics.join(",")),
+        !diagnostics.is_empty(),
+    )
+}
+
+/// Lexes and parses the input without running it, reporting every lexer error at once,
+/// or else every parser error
+fn collect_diagnostics(input: &str) -> Vec<MovaError> {
+    let (_, errors) = tokenize_all(input);
+    if !errors.is_empty() {
+        return errors;
+    }
+
+    match tokenize_with_spans(input, false) {
+        Ok(tokens) => parse_all(tokens, ParserOptions::default()).1,
+        Err(e) => vec![e],
+    }
+}
+
+fn diagnostic_json(path: &str, message: &str, position: Option<Position>) -> String {
+    let (line, character) = match position {
+        Some(position) => (position.line.to_string(), position.character.to_string()),
+        None => ("null".into(), "null".into()),
+    };
+    format!(
+        "{{\"file\":{},\"severity\":\"error\",\"message\":{},\"line\":{line},\"character\":{character}}}",
+        json_string(path),
+        json_string(message),
+    )
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(name: &str, content: &str) -> String {
+        let path = env::temp_dir().join(format!("mova-{}-{name}", std::process::id()));
+        fs::write(&path, content).expect("Unable to write test file");
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn it_continues_after_failed_file() {
+        let broken = write("continue-broken.mova", "1 +");
+        let valid = write("continue-valid.mova", "1 + 2");
+
+        assert_eq!(
+            run_files(&[&broken, &valid], false).0,
+            vec![broken.as_str()]
+        );
+        [broken, valid]
+            .iter()
+            .for_each(|path| fs::remove_file(path).unwrap());
+    }
+
+    #[test]
+    fn it_stops_at_first_failure_with_fail_fast() {
+        let first = write("fail-fast-first.mova", "1 +");
+        let second = write("fail-fast-second.mova", "missing");
+
+        assert_eq!(
+            run_files(&[&first, &second], false).0,
+            vec![first.as_str(), second.as_str()]
+        );
+        assert_eq!(run_files(&[&first, &second], true).0, vec![first.as_str()]);
+        [first, second]
+            .iter()
+            .for_each(|path| fs::remove_file(path).unwrap());
+    }
+
+    #[test]
+    fn it_exits_with_the_final_number() {
+        let path = write("exit-number.mova", "let x = 1; x + 2");
+        let (failed, result) = run_files(&[&path], false);
+        assert!(failed.is_empty());
+        assert_eq!(exit_code(result.as_ref()), 3);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_maps_other_results_to_exit_codes() {
+        assert_eq!(exit_code(Some(&Value::Number(259))), 3);
+        assert_eq!(exit_code(Some(&Value::Number(-1))), 255);
+        assert_eq!(exit_code(Some(&Value::Boolean(false))), 1);
+        assert_eq!(exit_code(Some(&Value::Boolean(true))), 0);
+        assert_eq!(exit_code(None), 0);
+    }
+
+    #[test]
+    fn it_reports_every_lexer_error_as_json() {
+        let path = write("diagnostics-lexer.mova", "1 @ 2\n3 # 4");
+        let (json, has_errors) = diagnose_files(&[&path]);
+
+        assert!(has_errors);
+        assert_eq!(
+            json,
+            format!(
+                "[{{\"file\":{file},\"severity\":\"error\",\"message\":\"Lexer error at 1:3: Unexpected character: '@'\",\"line\":1,\"character\":3}},\
+                 {{\"file\":{file},\"severity\":\"error\",\"message\":\"Lexer error at 2:3: Unexpected character: '#'\",\"line\":2,\"character\":3}}]",
+                file = json_string(&path),
+            )
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_reports_every_parser_error_with_its_column() {
+        let path = write("diagnostics-parser.mova", "let = 1\nlet x = (1 +");
+        let (json, has_errors) = diagnose_files(&[&path]);
+
+        assert!(has_errors);
+        assert_eq!(
+            json,
+            format!(
+                "[{{\"file\":{file},\"severity\":\"error\",\"message\":\"Parser error at 1:5: Expected an identifier but found '='\",\"line\":1,\"character\":5}},\
+                 {{\"file\":{file},\"severity\":\"error\",\"message\":\"Parser error at 2:13: Unexpected end of input\",\"line\":2,\"character\":13}}]",
+                file = json_string(&path),
+            )
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_diagnoses_without_running() {
+        let path = write(
+            "diagnostics-valid.mova",
+            "let x = input()\nwhile true {}\nmissing",
+        );
+        assert_eq!(diagnose_files(&[&path]), ("[]".to_string(), false));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_reports_unreadable_file_without_position() {
+        let (json, has_errors) = diagnose_files(&["diagnostics-missing.mova"]);
+        assert!(has_errors);
+        assert!(json.ends_with("\"line\":null,\"character\":null}]"));
+    }
+
+    #[test]
+    fn it_escapes_json_strings() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
 }
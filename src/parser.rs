@@ -1,5 +1,12 @@
-pub use crate::parser::node::parse;
+pub use crate::parser::{
+    node::{Program, parse, parse_all, parse_statements, parse_with, parse_with_spans},
+    precedence::PrecedenceTable,
+    stream::ParserOptions,
+};
 
 pub mod expression;
 pub mod node;
+pub mod precedence;
 pub mod statement;
+pub mod stream;
+pub mod visit;
@@ -1,11 +1,25 @@
-use std::rc::Rc;
+use std::{fmt, num::IntErrorKind, rc::Rc};
 
 use crate::{
-    error::{MovaError, ParserError, Result},
-    lexer::Token,
-    parser::{node::Node, statement::parse_statement},
+    error::{ParserError, Result},
+    lexer::{Operator, Token},
+    parser::{node::Node, statement::parse_statement, stream::TokenStream},
 };
 
+/// An argument passed by parameter name, as in `f(x = 1)`
+#[derive(Clone, Debug)]
+pub struct NamedArgument {
+    pub name: Rc<String>,
+    pub value: Expression,
+}
+
+/// The arguments of a call, where named ones always follow the positional ones
+#[derive(Clone, Debug, Default)]
+pub struct Arguments {
+    pub positional: Vec<Expression>,
+    pub named: Vec<NamedArgument>,
+}
+
 #[derive(Clone, Debug)]
 pub enum Expression {
     Number(i32),
@@ -22,9 +36,19 @@ pub enum Expression {
     },
     Call {
         name: Rc<String>,
-        arguments: Rc<[Expression]>,
+        arguments: Rc<Arguments>,
+    },
+    /// `target[index]`, where the target may itself be an index or any other operand
+    Index {
+        target: Rc<Expression>,
+        index: Rc<Expression>,
     },
     Dereference(Rc<Expression>),
+    BitwiseNot(Rc<Expression>),
+    Negate(Rc<Expression>),
+    /// `do expr`, evaluated in its own child scope like a one-expression block
+    Do(Rc<Expression>),
+    Tuple(Rc<[Expression]>),
     Block(Rc<[Node]>),
     If {
         condition: Rc<Expression>,
@@ -35,157 +59,291 @@ pub enum Expression {
         condition: Rc<Expression>,
         body: Rc<Expression>,
     },
+    /// `return` or `return value`, leaving the enclosing function early
+    Return(Option<Rc<Expression>>),
     Program(Rc<[Node]>),
 }
 
-fn get_infix_binding_power(operator: &str) -> Option<(u8, u8)> {
-    match operator {
-        "==" | "<" | ">" => Some((1, 2)),
-        "+" | "-" => Some((3, 4)),
-        "*" | "/" => Some((5, 6)),
-        _ => None,
+impl fmt::Display for Arguments {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let positional = self.positional.iter().map(Expression::to_string);
+        let named = self
+            .named
+            .iter()
+            .map(|a| format!("{} = {}", a.name, a.value));
+        let arguments: Vec<String> = positional.chain(named).collect();
+        write!(f, "{}", arguments.join(", "))
     }
 }
 
-fn get_postfix_binding_power(operator: &str) -> Option<(u8, ())> {
-    match operator {
-        "(" => Some((2, ())),
-        _ => None,
+/// Renders an approximate, fully parenthesized source form meant for messages, not for
+/// reformatting code
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Number(n) => write!(f, "{n}"),
+            Expression::Boolean(b) => write!(f, "{b}"),
+            Expression::Identifier(name) => write!(f, "{name}"),
+            Expression::Reference { data, is_mutable } => {
+                let prefix = if *is_mutable { "&mut " } else { "&" };
+                write!(f, "{prefix}{data}")
+            }
+            Expression::BinaryExpression {
+                operator,
+                left,
+                right,
+            } => write!(f, "({left} {operator} {right})"),
+            Expression::Call { name, arguments } => write!(f, "{name}({arguments})"),
+            Expression::Index { target, index } => write!(f, "{target}[{index}]"),
+            Expression::Dereference(inner) => write!(f, "*{inner}"),
+            Expression::BitwiseNot(inner) => write!(f, "~{inner}"),
+            Expression::Negate(inner) => write!(f, "-{inner}"),
+            Expression::Do(inner) => write!(f, "do {inner}"),
+            Expression::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(Expression::to_string).collect();
+                match elements.as_slice() {
+                    [element] => write!(f, "({element},)"),
+                    elements => write!(f, "({})", elements.join(", ")),
+                }
+            }
+            Expression::Block(nodes) if nodes.is_empty() => write!(f, "{{}}"),
+            Expression::Block(nodes) => {
+                let nodes: Vec<String> = nodes.iter().map(Node::to_string).collect();
+                write!(f, "{{ {} }}", nodes.join("; "))
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                write!(f, "if {condition} {consequence}")?;
+                match alternative {
+                    Some(alternative) => write!(f, " else {alternative}"),
+                    None => Ok(()),
+                }
+            }
+            Expression::While { condition, body } => write!(f, "while {condition} {body}"),
+            Expression::Return(Some(value)) => write!(f, "return {value}"),
+            Expression::Return(None) => write!(f, "return"),
+            Expression::Program(nodes) => {
+                let nodes: Vec<String> = nodes.iter().map(Node::to_string).collect();
+                write!(f, "{}", nodes.join("; "))
+            }
+        }
+    }
+}
+
+/// Splits a number literal into its digits and their radix
+fn split_radix(literal: &str) -> (&str, u32) {
+    match literal.get(..2) {
+        Some("0x") => (&literal[2..], 16),
+        Some("0o") => (&literal[2..], 8),
+        Some("0b") => (&literal[2..], 2),
+        _ => (literal, 10),
     }
 }
 
-fn parse_call(tokens: &mut Vec<Token>, left: Expression) -> Result<Expression> {
+fn parse_number(literal: String) -> std::result::Result<i32, ParserError> {
+    let (digits, radix) = split_radix(&literal);
+    i32::from_str_radix(digits, radix).map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+            ParserError::NumericLiteralOutOfRange(literal)
+        }
+        _ => ParserError::InvalidNumber(literal),
+    })
+}
+
+fn parse_arguments(tokens: &mut TokenStream) -> Result<Arguments> {
     tokens.pop();
-    let mut parameters = Vec::new();
+    let mut arguments = Arguments::default();
 
     loop {
+        if arguments.positional.len() + arguments.named.len() > tokens.max_arguments() {
+            return Err(tokens.error(ParserError::TooManyArguments(tokens.max_arguments())));
+        }
         match tokens.last() {
-            Some(Token::Operator(o)) if o == ")" => {
+            Token::Operator(Operator::RParen) => {
                 tokens.pop();
                 break;
             }
-            Some(Token::SpecialCharacter(')')) => {
+            Token::SpecialCharacter(')') => {
                 tokens.pop();
                 break;
             }
-            Some(_) => {
-                let argument = parse_expression(tokens)?;
-                parameters.push(argument);
-
-                match tokens.last() {
-                    Some(Token::SpecialCharacter(',')) => {
-                        tokens.pop();
-                    }
-                    Some(Token::Operator(o)) if o == ")" => {}
-                    Some(Token::SpecialCharacter(')')) => {}
-                    None => {
-                        return Err(MovaError::Parser(
-                            ParserError::ExpectedArgumentListToBeClosed,
-                        ));
-                    }
-                    _ => {
-                        return Err(MovaError::Parser(
-                            ParserError::ExpectedCommaOrArgumentListToBeClosed,
-                        ));
-                    }
-                }
+            Token::Identifier(name) if *tokens.second() == Token::Assignment => {
+                let name = name.clone();
+                let name = tokens.intern(name);
+                tokens.pop();
+                tokens.pop();
+                arguments.named.push(NamedArgument {
+                    name,
+                    value: parse_expression(tokens)?,
+                });
+                expect_argument_separator(tokens)?;
+            }
+            Token::Eof => {
+                return Err(tokens.error(ParserError::ExpectedArgumentListToBeClosed));
             }
-            None => {
-                return Err(MovaError::Parser(
-                    ParserError::ExpectedArgumentListToBeClosed,
-                ));
+            _ => {
+                if !arguments.named.is_empty() {
+                    return Err(tokens.error(ParserError::PositionalArgumentAfterNamed));
+                }
+                let argument = parse_expression(tokens)?;
+                arguments.positional.push(argument);
+                expect_argument_separator(tokens)?;
             }
         }
     }
 
-    match left {
-        Expression::Identifier(i) => Ok(Expression::Call {
-            name: i,
-            arguments: parameters.into(),
-        }),
-        e => Err(MovaError::Parser(ParserError::ExpectedIdentifierToBeCalled(format!("{e:?}")))),
+    Ok(arguments)
+}
+
+/// Consumes the `,` after an argument, or checks that the argument list ends there
+fn expect_argument_separator(tokens: &mut TokenStream) -> Result<()> {
+    match tokens.last() {
+        Token::SpecialCharacter(',') => {
+            tokens.pop();
+        }
+        Token::Operator(Operator::RParen) => {}
+        Token::SpecialCharacter(')') => {}
+        Token::Eof => {
+            return Err(tokens.error(ParserError::ExpectedArgumentListToBeClosed));
+        }
+        _ => {
+            return Err(tokens.error(ParserError::ExpectedCommaOrArgumentListToBeClosed));
+        }
+    }
+    Ok(())
+}
+
+fn parse_call(tokens: &mut TokenStream, left: Expression) -> Result<Expression> {
+    let Expression::Identifier(name) = left else {
+        return Err(tokens.error(ParserError::ExpectedIdentifierToBeCalled(left.to_string())));
+    };
+    Ok(Expression::Call {
+        name,
+        arguments: Rc::new(parse_arguments(tokens)?),
+    })
+}
+
+/// Parses the `[index]` applied to `target`
+fn parse_index(tokens: &mut TokenStream, target: Expression) -> Result<Expression> {
+    tokens.pop();
+    let index = parse_expression(tokens)?;
+    match tokens.pop() {
+        Token::Operator(Operator::RBracket) => {}
+        Token::Eof => return Err(tokens.error(ParserError::UnexpectedEndOfInput)),
+        t => {
+            return Err(tokens.error_at_previous(ParserError::UnexpectedToken {
+                found: Box::new(t),
+                expected: "']' after an index",
+            }));
+        }
+    }
+    Ok(Expression::Index {
+        target: Rc::new(target),
+        index: Rc::new(index),
+    })
+}
+
+/// Desugars `receiver.name(arguments)` into `name(receiver, arguments)`
+fn parse_method_call(tokens: &mut TokenStream, receiver: Expression) -> Result<Expression> {
+    tokens.pop();
+    let name = match tokens.pop() {
+        Token::Identifier(i) => i,
+        Token::Eof => return Err(tokens.error(ParserError::UnexpectedEndOfInput)),
+        t => {
+            return Err(tokens.error_at_previous(ParserError::UnexpectedToken {
+                found: Box::new(t),
+                expected: "a method name after '.'",
+            }));
+        }
+    };
+    match tokens.last() {
+        Token::Operator(Operator::LParen) => {}
+        Token::SpecialCharacter('(') => {}
+        _ => {
+            return Err(tokens.error(ParserError::ExpectedArgumentListAfterMethodName(name)));
+        }
     }
+
+    let mut arguments = parse_arguments(tokens)?;
+    arguments.positional.insert(0, receiver);
+    Ok(Expression::Call {
+        name: tokens.intern(name),
+        arguments: Rc::new(arguments),
+    })
+}
+
+fn parse_binary_expression(tokens: &mut TokenStream, binding_power: u8) -> Result<Expression> {
+    tokens.nested(|tokens| parse_binary_expression_inner(tokens, binding_power))
 }
 
-fn parse_binary_expression(tokens: &mut Vec<Token>, binding_power: u8) -> Result<Expression> {
+fn parse_binary_expression_inner(
+    tokens: &mut TokenStream,
+    binding_power: u8,
+) -> Result<Expression> {
     let mut left = match tokens.last() {
-        Some(Token::Operator(op)) if op == "&" => {
+        Token::Operator(Operator::Amp) => {
             tokens.pop();
             parse_reference(tokens)?
         }
-        Some(Token::Operator(op)) if op == "*" => {
+        // `&&` in prefix position borrows twice, as in `&&x`
+        Token::Operator(Operator::AmpAmp) => {
             tokens.pop();
-            Expression::Dereference(Rc::new(parse_binary_expression(tokens, 7)?))
+            Expression::Reference {
+                data: Rc::new(parse_reference(tokens)?),
+                is_mutable: false,
+            }
         }
-        Some(Token::Operator(op)) if op == "(" => {
+        Token::Operator(Operator::Star | Operator::Tilde | Operator::Minus) => {
+            parse_prefix_operator(tokens)?
+        }
+        Token::Operator(Operator::LParen) => {
             tokens.pop();
-            let expr = parse_expression(tokens)?;
-            match tokens.pop() {
-                Some(Token::Operator(op)) if op == ")" => Ok(expr),
-                Some(t) => Err(MovaError::Parser(ParserError::ExpectedClosingParenthesis(format!("{t:?}")))),
-                None => Err(MovaError::Parser(ParserError::ExpectedClosingParenthesisButFoundEndOfInput)),
-            }?
+            parse_group(tokens)?
         }
         _ => match tokens.pop() {
-            Some(Token::Identifier(i)) => Expression::Identifier(Rc::new(i)),
-            Some(Token::Number(n)) => Expression::Number(
-                n.parse()
-                    .map_err(|_| MovaError::Parser(ParserError::InvalidNumber(n)))?,
-            ),
-            Some(Token::Boolean(b)) => Expression::Boolean(b),
-            Some(Token::Keyword(k)) if k == "if" => {
-                let condition = Rc::new(parse_expression(tokens)?);
-                let consequence = Rc::new(parse_block(tokens)?);
-                let alternative = match tokens.last() {
-                    Some(Token::Keyword(k)) if k == "else" => {
-                        tokens.pop();
-                        if let Some(Token::Keyword(next_k)) = tokens.last() {
-                            if next_k == "if" {
-                                Some(Rc::new(parse_expression(tokens)?))
-                            } else {
-                                Some(Rc::new(parse_block(tokens)?))
-                            }
-                        } else {
-                            Some(Rc::new(parse_block(tokens)?))
-                        }
-                    }
-                    _ => None,
-                };
-                Expression::If {
-                    condition,
-                    consequence,
-                    alternative,
-                }
+            Token::Identifier(i) => Expression::Identifier(tokens.intern(i)),
+            Token::Number(n) => {
+                Expression::Number(parse_number(n).map_err(|e| tokens.error_at_previous(e))?)
             }
-            Some(Token::Keyword(k)) if k == "while" => {
-                let condition = Rc::new(parse_expression(tokens)?);
-                let body = Rc::new(parse_block(tokens)?);
-                Expression::While { condition, body }
+            Token::Boolean(b) => Expression::Boolean(b),
+            Token::Keyword(k) if k == "if" => parse_if(tokens)?,
+            Token::Keyword(k) if k == "while" => parse_while(tokens)?,
+            Token::Keyword(k) if k == "return" => parse_return(tokens)?,
+            Token::Keyword(k) if k == "do" => Expression::Do(Rc::new(parse_expression(tokens)?)),
+            Token::Eof => {
+                return Err(tokens.error(ParserError::UnexpectedEndOfInput));
             }
-            Some(t) => {
-                return Err(MovaError::Parser(ParserError::UnexpectedToken(format!("{t:?}"))));
-            }
-            None => {
-                return Err(MovaError::Parser(ParserError::UnexpectedEndOfInput));
+            t => {
+                return Err(tokens.error_at_previous(ParserError::UnexpectedToken {
+                    found: Box::new(t),
+                    expected: "an expression",
+                }));
             }
         },
     };
 
-    while let Some(t) = tokens.last().cloned() {
+    loop {
+        let t = tokens.last().clone();
+        if tokens.after_newline() {
+            break;
+        }
         match t {
             Token::Operator(o) => {
-                if let Some((lbp, ())) = get_postfix_binding_power(&o) {
+                if let Some(lbp) = tokens.precedence().postfix(o.as_str()) {
                     if lbp < binding_power {
                         break;
                     }
-                    if o == "(" {
-                        left = parse_call(tokens, left)?;
-                    }
+                    left = parse_postfix_operator(tokens, o, left)?;
                     continue;
                 }
 
-                if let Some((lbp, rbp)) = get_infix_binding_power(&o) {
-                    if lbp < binding_power {
+                if let Some((lbp, rbp)) = tokens.precedence().infix(o.as_str()) {
+                    // An operator that can also be a prefix starts the next statement when it
+                    // opens a line, as in a block ending with `&local` on its own line
+                    if lbp < binding_power || (is_prefix(o) && tokens.starts_line()) {
                         break;
                     }
 
@@ -194,7 +352,7 @@ fn parse_binary_expression(tokens: &mut Vec<Token>, binding_power: u8) -> Result
                     left = Expression::BinaryExpression {
                         left: Rc::new(left),
                         right,
-                        operator: Rc::new(o),
+                        operator: Rc::new(o.to_string()),
                     };
                     continue;
                 }
@@ -202,7 +360,7 @@ fn parse_binary_expression(tokens: &mut Vec<Token>, binding_power: u8) -> Result
                 break;
             }
             Token::SpecialCharacter('(') => {
-                if let Some((lbp, ())) = get_postfix_binding_power("(") {
+                if let Some(lbp) = tokens.precedence().postfix("(") {
                     if lbp < binding_power {
                         break;
                     }
@@ -218,43 +376,612 @@ fn parse_binary_expression(tokens: &mut Vec<Token>, binding_power: u8) -> Result
     Ok(left)
 }
 
-fn parse_reference(tokens: &mut Vec<Token>) -> Result<Expression> {
-    let is_mutable = matches!(tokens.last(), Some(Token::Keyword(k)) if k == "mut");
+/// Applies a call, index or method call to the expression before it
+fn parse_postfix_operator(
+    tokens: &mut TokenStream,
+    operator: Operator,
+    left: Expression,
+) -> Result<Expression> {
+    match operator {
+        Operator::Dot => parse_method_call(tokens, left),
+        Operator::LBracket => parse_index(tokens, left),
+        _ => parse_call(tokens, left),
+    }
+}
+
+/// Whether the operator can also start an expression, as in `&x`, `&&x`, `*x`, `~x` and `-x`
+fn is_prefix(operator: Operator) -> bool {
+    matches!(
+        operator,
+        Operator::Amp | Operator::AmpAmp | Operator::Star | Operator::Tilde | Operator::Minus
+    )
+}
+
+/// The negation of a literal too large for `i32` on its own, i.e. `i32::MIN` written
+/// as `-2147483648`
+fn parse_negated_number(literal: &str) -> Option<i32> {
+    let (digits, radix) = split_radix(literal);
+    let magnitude = u32::from_str_radix(digits, radix).ok()?;
+    match i32::try_from(magnitude) {
+        Ok(_) => None,
+        Err(_) => 0i32.checked_sub_unsigned(magnitude),
+    }
+}
+
+/// Parses `*x` as a dereference, `~x` as a bitwise not and `-x` as a negation
+fn parse_prefix_operator(tokens: &mut TokenStream) -> Result<Expression> {
+    let operator = tokens.pop();
+    if let (Token::Operator(Operator::Minus), Token::Number(literal)) = (&operator, tokens.last())
+        && let Some(n) = parse_negated_number(literal)
+    {
+        tokens.pop();
+        return Ok(Expression::Number(n));
+    }
+    let power = tokens.precedence().prefix();
+    let operand = Rc::new(parse_binary_expression(tokens, power)?);
+
+    match operator {
+        Token::Operator(Operator::Tilde) => Ok(Expression::BitwiseNot(operand)),
+        Token::Operator(Operator::Minus) => Ok(Expression::Negate(operand)),
+        _ => Ok(Expression::Dereference(operand)),
+    }
+}
+
+/// Parses the rest of `(a)` as grouping, or of `(a, b)` and `(a,)` as a tuple
+fn parse_group(tokens: &mut TokenStream) -> Result<Expression> {
+    let first = parse_expression(tokens)?;
+    let mut elements = Vec::new();
+
+    while let Token::SpecialCharacter(',') = tokens.last() {
+        tokens.pop();
+        if elements.is_empty() {
+            elements.push(first.clone());
+        }
+        if matches!(tokens.last(), Token::Operator(Operator::RParen)) {
+            break;
+        }
+        elements.push(parse_expression(tokens)?);
+    }
+
+    match tokens.pop() {
+        Token::Operator(Operator::RParen) => {}
+        Token::Eof => {
+            return Err(tokens.error(ParserError::UnbalancedParen {
+                found: Box::new(Token::Eof),
+            }));
+        }
+        found => {
+            return Err(tokens.error_at_previous(ParserError::UnbalancedParen {
+                found: Box::new(found),
+            }));
+        }
+    }
+
+    if elements.is_empty() {
+        Ok(first)
+    } else {
+        Ok(Expression::Tuple(elements.into()))
+    }
+}
+
+fn parse_if(tokens: &mut TokenStream) -> Result<Expression> {
+    let condition = Rc::new(parse_expression(tokens)?);
+    let consequence = Rc::new(parse_block(tokens)?);
+    let alternative = match tokens.last() {
+        Token::Keyword(k) if k == "else" => {
+            tokens.pop();
+            if let Token::Keyword(next_k) = tokens.last() {
+                if next_k == "if" {
+                    Some(Rc::new(parse_expression(tokens)?))
+                } else {
+                    Some(Rc::new(parse_block(tokens)?))
+                }
+            } else {
+                Some(Rc::new(parse_block(tokens)?))
+            }
+        }
+        _ => None,
+    };
+    Ok(Expression::If {
+        condition,
+        consequence,
+        alternative,
+    })
+}
+
+fn parse_while(tokens: &mut TokenStream) -> Result<Expression> {
+    let condition = Rc::new(parse_expression(tokens)?);
+    let body = Rc::new(parse_block(tokens)?);
+    Ok(Expression::While { condition, body })
+}
+
+/// Parses the value after `return`, which is left out when the statement ends right away
+fn parse_return(tokens: &mut TokenStream) -> Result<Expression> {
+    let ends = matches!(
+        tokens.last(),
+        Token::SpecialCharacter(';' | '}') | Token::Dedent | Token::Eof
+    );
+    if ends || tokens.after_newline() {
+        return Ok(Expression::Return(None));
+    }
+    Ok(Expression::Return(Some(Rc::new(parse_expression(tokens)?))))
+}
+
+fn parse_reference(tokens: &mut TokenStream) -> Result<Expression> {
+    let is_mutable = matches!(tokens.last(), Token::Keyword(k) if k == "mut");
     if is_mutable {
         tokens.pop();
     }
-    let right = parse_binary_expression(tokens, 7)?;
+    let power = tokens.precedence().prefix();
+    let right = parse_binary_expression(tokens, power)?;
     Ok(Expression::Reference {
         data: Rc::new(right),
         is_mutable,
     })
 }
 
-fn parse_block(tokens: &mut Vec<Token>) -> Result<Expression> {
-    match tokens.last() {
-        Some(Token::SpecialCharacter('{')) => {
-            tokens.pop();
-            let mut body = Vec::new();
-
-            loop {
-                match tokens.last() {
-                    Some(Token::SpecialCharacter('}')) => break,
-                    Some(_) => body.push(parse_statement(tokens)?),
-                    None => {
-                        return Err(MovaError::Parser(ParserError::ExpectedBlockToBeClosed));
-                    }
+/// Parses a block delimited by braces or, when indentation is significant, by an indented
+/// run of lines, and any other expression otherwise
+fn parse_block(tokens: &mut TokenStream) -> Result<Expression> {
+    let close = match tokens.last() {
+        Token::SpecialCharacter('{') => Token::SpecialCharacter('}'),
+        Token::Indent => Token::Dedent,
+        _ => return parse_binary_expression(tokens, 0),
+    };
+
+    tokens.nested(|tokens| {
+        // Remember where the block starts so an unbalanced brace can be located
+        let unclosed = ParserError::UnbalancedBrace {
+            opened_on: tokens.span().map(|span| span.line),
+        };
+        tokens.pop();
+        let mut body = Vec::new();
+
+        loop {
+            match tokens.last() {
+                token if *token == close => break,
+                Token::Eof => return Err(tokens.error(unclosed)),
+                _ => body.push(parse_statement(tokens)?),
+            }
+        }
+
+        tokens.pop();
+        Ok(Expression::Block(body.into()))
+    })
+}
+
+pub fn parse_expression(tokens: &mut TokenStream) -> Result<Expression> {
+    parse_block(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::MovaError,
+        lexer::{tokenize, tokenize_with_spans},
+        parser::{ParserOptions, PrecedenceTable, parse, parse_with, parse_with_spans},
+    };
+
+    #[test]
+    fn it_rejects_out_of_range_number() -> Result<()> {
+        let result = parse(tokenize("99999999999")?);
+        match result {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Parser error: Numeric literal out of range for i32: 99999999999"
+            ),
+            Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_largest_number() -> Result<()> {
+        assert_eq!(parse_number("2147483647".into())?, i32::MAX);
+        Ok(())
+    }
+
+    #[test]
+    fn it_folds_negated_smallest_number() -> Result<()> {
+        for input in ["-2147483648", "-0x80000000"] {
+            let Node::Expression(program) = parse(tokenize(input)?)? else {
+                panic!("Expected program expression");
+            };
+            let Expression::Program(body) = &*program else {
+                panic!("Expected program but got {program:?}");
+            };
+            assert!(
+                matches!(&body[0], Node::Expression(e) if matches!(**e, Expression::Number(i32::MIN))),
+                "{input} parsed as {body:?}"
+            );
+        }
+        // Only a literal that does not fit on its own is folded
+        let Node::Expression(program) = parse(tokenize("-5")?)? else {
+            panic!("Expected program expression");
+        };
+        assert!(
+            matches!(&*program, Expression::Program(body) if matches!(&body[0], Node::Expression(e) if matches!(**e, Expression::Negate(_))))
+        );
+        assert!(parse(tokenize("-2147483649")?).is_err());
+        assert!(parse(tokenize("2147483648")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_prefixed_numbers() -> Result<()> {
+        assert_eq!(parse_number("0xff".into())?, 255);
+        assert_eq!(parse_number("0o17".into())?, 15);
+        assert_eq!(parse_number("0b1010".into())?, 10);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_out_of_range_prefixed_number() -> Result<()> {
+        let result = parse(tokenize("0xFFFFFFFF")?);
+        match result {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Parser error: Numeric literal out of range for i32: 0xFFFFFFFF"
+            ),
+            Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_invalid_digits_for_radix() -> Result<()> {
+        for literal in ["0b102", "0xfg", "0x"] {
+            match parse(tokenize(literal)?) {
+                Err(e) => assert_eq!(
+                    e.to_string(),
+                    format!("Parser error: Invalid number: {literal}")
+                ),
+                Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_if_without_else() -> Result<()> {
+        let Node::Expression(program) = parse(tokenize("if x < 1 { y } 3")?)? else {
+            panic!("Expected program expression");
+        };
+        let Expression::Program(body) = &*program else {
+            panic!("Expected program but got {program:?}");
+        };
+
+        assert_eq!(body.len(), 2);
+        match &body[0] {
+            Node::Expression(e) => match &**e {
+                Expression::If {
+                    condition,
+                    consequence,
+                    alternative: None,
+                } => {
+                    assert!(matches!(**condition, Expression::BinaryExpression { .. }));
+                    assert!(matches!(**consequence, Expression::Block(_)));
+                }
+                e => panic!("Expected if without else but got {e:?}"),
+            },
+            n => panic!("Expected expression but got {n:?}"),
+        }
+        assert!(matches!(&body[1], Node::Expression(e) if matches!(**e, Expression::Number(3))));
+        Ok(())
+    }
+
+    #[test]
+    fn it_desugars_method_call() -> Result<()> {
+        let method = parse(tokenize("x.f(y)")?)?;
+        let call = parse(tokenize("f(x, y)")?)?;
+        assert_eq!(format!("{method:?}"), format!("{call:?}"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_chains_method_calls_left_to_right() -> Result<()> {
+        let method = parse(tokenize("x.f().g(1) + 2")?)?;
+        let call = parse(tokenize("g(f(x), 1) + 2")?)?;
+        assert_eq!(format!("{method:?}"), format!("{call:?}"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_named_arguments_after_positional() -> Result<()> {
+        let Node::Expression(program) = parse(tokenize("f(1, x = 2, y = a == b)")?)? else {
+            panic!("Expected program expression");
+        };
+        let Expression::Program(body) = &*program else {
+            panic!("Expected program but got {program:?}");
+        };
+        match &body[0] {
+            Node::Expression(e) => match &**e {
+                Expression::Call { arguments, .. } => {
+                    assert_eq!(arguments.positional.len(), 1);
+                    let names: Vec<&str> =
+                        arguments.named.iter().map(|n| n.name.as_str()).collect();
+                    assert_eq!(names, ["x", "y"]);
+                    assert!(matches!(
+                        arguments.named[1].value,
+                        Expression::BinaryExpression { .. }
+                    ));
                 }
+                e => panic!("Expected call but got {e:?}"),
+            },
+            n => panic!("Expected expression but got {n:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_positional_argument_after_named() -> Result<()> {
+        match parse(tokenize("f(x = 1, 2)")?) {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Parser error: Positional arguments must come before named arguments"
+            ),
+            Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_method_without_arguments() -> Result<()> {
+        match parse(tokenize("x.f")?) {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Parser error: Expected argument list after method name 'f'"
+            ),
+            Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_line_of_unclosed_block() -> Result<()> {
+        let input = "let x = 1\nfn f(a) = {\n    let b = a\n    if b < 2 { b }\n\nf(x)";
+        let result = parse_with_spans(tokenize_with_spans(input, false)?, ParserOptions::default());
+        match result {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Parser error at 6:5: Expected block opened on line 2 to be closed"
+            ),
+            Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_structured_error_kinds() -> Result<()> {
+        match parse(tokenize("1 + )")?) {
+            Err(MovaError::Parser {
+                error:
+                    ParserError::UnexpectedToken {
+                        found,
+                        expected: "an expression",
+                    },
+                ..
+            }) if *found == Token::Operator(Operator::RParen) => {}
+            result => panic!("Expected an unexpected token but got {result:?}"),
+        }
+        match parse(tokenize("(1 + 2")?) {
+            Err(MovaError::Parser {
+                error: ParserError::UnbalancedParen { found },
+                ..
+            }) if *found == Token::Eof => {}
+            result => panic!("Expected an unbalanced parenthesis but got {result:?}"),
+        }
+        match parse_with_spans(tokenize_with_spans("{ 1", false)?, ParserOptions::default()) {
+            Err(MovaError::Parser {
+                error: ParserError::UnbalancedBrace { opened_on: Some(1) },
+                ..
+            }) => {}
+            result => panic!("Expected an unbalanced brace but got {result:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_locates_unexpected_end_of_input() -> Result<()> {
+        let result = parse_with_spans(
+            tokenize_with_spans("let x = (1 +", false)?,
+            ParserOptions::default(),
+        );
+        match result {
+            Err(e) => {
+                assert_eq!(
+                    e.to_string(),
+                    "Parser error at 1:13: Unexpected end of input"
+                );
+                assert!(e.position().is_some());
             }
+            Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_orders_bitwise_operators_between_comparison_and_arithmetic() -> Result<()> {
+        let flat = parse(tokenize("a == b | c ^ d & e << f + g")?)?;
+        let grouped = parse(tokenize("a == (b | (c ^ (d & (e << (f + g)))))")?)?;
+        assert_eq!(format!("{flat:?}"), format!("{grouped:?}"));
+        Ok(())
+    }
 
-            match tokens.pop() {
-                Some(Token::SpecialCharacter('}')) => Ok(Expression::Block(body.into())),
-                _ => Err(MovaError::Parser(ParserError::ExpectedBlockToBeClosed)),
+    #[test]
+    fn it_borrows_with_ampersand_starting_a_line() -> Result<()> {
+        let input = "let x = 1\n&x";
+        let spanned =
+            parse_with_spans(tokenize_with_spans(input, false)?, ParserOptions::default())?;
+        let separate = parse(tokenize("let x = 1; &x")?)?;
+        assert_eq!(format!("{spanned:?}"), format!("{separate:?}"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_starts_statements_with_prefix_operators_opening_a_line() -> Result<()> {
+        let cases = [
+            ("let y = &x\n*y", "let y = &x; *y"),
+            ("let y = x\n-y", "let y = x; -y"),
+            ("let y = x\n&&y", "let y = x; &&y"),
+            ("let b = 3\n&a + &b", "let b = 3; &a + &b"),
+            ("let b = 3 -\na", "let b = (3 - a)"),
+        ];
+        for (input, expected) in cases {
+            let spanned =
+                parse_with_spans(tokenize_with_spans(input, false)?, ParserOptions::default())?;
+            let expected = parse(tokenize(expected)?)?;
+            assert_eq!(format!("{spanned:?}"), format!("{expected:?}"), "{input}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_infix_operators_regardless_of_spacing() -> Result<()> {
+        let cases = [
+            ("n -1", "(n - 1)"),
+            ("3 -a", "(3 - a)"),
+            ("a *a", "(a * a)"),
+            ("3 &a + &b", "(3 & (a + &b))"),
+            ("3&a", "(3 & a)"),
+            ("a - b", "(a - b)"),
+        ];
+        for (input, expected) in cases {
+            let spanned =
+                parse_with_spans(tokenize_with_spans(input, false)?, ParserOptions::default())?;
+            let plain = parse(tokenize(input)?)?;
+            assert_eq!(spanned.to_string(), expected, "{input}");
+            assert_eq!(format!("{spanned:?}"), format!("{plain:?}"), "{input}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_binds_calls_tighter_than_infix_operators() -> Result<()> {
+        let program = parse(tokenize("f(1) + g(2) * -h(3)")?)?;
+        assert_eq!(program.to_string(), "(f(1) + (g(2) * -h(3)))");
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_index_chains_left_to_right() -> Result<()> {
+        let program = parse(tokenize("a[0][1]")?)?;
+        let Node::Expression(program) = program else {
+            panic!("Expected expression");
+        };
+        let Expression::Program(body) = &*program else {
+            panic!("Expected program");
+        };
+        let Node::Expression(outer) = &body[0] else {
+            panic!("Expected expression");
+        };
+        let Expression::Index { target, index } = &**outer else {
+            panic!("Expected index but got {outer:?}");
+        };
+        assert!(matches!(**index, Expression::Number(1)));
+        let Expression::Index { target, index } = &**target else {
+            panic!("Expected index but got {target:?}");
+        };
+        assert!(matches!(**index, Expression::Number(0)));
+        assert!(matches!(&**target, Expression::Identifier(name) if name.as_str() == "a"));
+
+        let program = parse(tokenize("get_row(0)[1] + m[i + 1]")?)?;
+        assert_eq!(program.to_string(), "(get_row(0)[1] + m[(i + 1)])");
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_unclosed_index() {
+        assert!(parse(tokenize("a[0").unwrap()).is_err());
+        assert!(parse(tokenize("a[0 1]").unwrap()).is_err());
+    }
+
+    #[test]
+    fn it_parses_with_custom_precedence() -> Result<()> {
+        let top_operator = |node: Node| match node {
+            Node::Expression(e) => match &*e {
+                Expression::Program(body) => match &body[0] {
+                    Node::Expression(e) => match &**e {
+                        Expression::BinaryExpression { operator, .. } => operator.to_string(),
+                        e => panic!("Expected binary expression but got {e:?}"),
+                    },
+                    n => panic!("Expected expression but got {n:?}"),
+                },
+                e => panic!("Expected program but got {e:?}"),
+            },
+            n => panic!("Expected expression but got {n:?}"),
+        };
+
+        assert_eq!(top_operator(parse(tokenize("1 + 2 * 3")?)?), "+");
+
+        let options = ParserOptions {
+            precedence: PrecedenceTable::default()
+                .with_infix("+", 7, 8)
+                .with_infix("*", 5, 6),
+            ..ParserOptions::default()
+        };
+        assert_eq!(
+            top_operator(parse_with(tokenize("1 + 2 * 3")?, options)?),
+            "*"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_shares_one_rc_per_identifier() -> Result<()> {
+        let Node::Expression(program) = parse(tokenize("x + x; f(x)")?)? else {
+            panic!("Expected program expression");
+        };
+        let Expression::Program(body) = &*program else {
+            panic!("Expected program but got {program:?}");
+        };
+        let (Node::Expression(sum), Node::Expression(call)) = (&body[0], &body[1]) else {
+            panic!("Expected two expressions but got {body:?}");
+        };
+        let Expression::BinaryExpression { left, right, .. } = &**sum else {
+            panic!("Expected binary expression but got {sum:?}");
+        };
+        let Expression::Call { arguments, .. } = &**call else {
+            panic!("Expected call but got {call:?}");
+        };
+
+        match (&**left, &**right, &arguments.positional[0]) {
+            (Expression::Identifier(a), Expression::Identifier(b), Expression::Identifier(c)) => {
+                assert!(Rc::ptr_eq(a, b));
+                assert!(Rc::ptr_eq(a, c));
             }
+            e => panic!("Expected identifiers but got {e:?}"),
         }
-        _ => parse_binary_expression(tokens, 0),
+        Ok(())
     }
-}
 
-pub fn parse_expression(tokens: &mut Vec<Token>) -> Result<Expression> {
-    parse_block(tokens)
+    #[test]
+    fn it_displays_expressions_as_source() -> Result<()> {
+        let cases = [
+            ("1 + 2 * 3", "(1 + (2 * 3))"),
+            ("f(&x, &mut y, k = -1)", "f(&x, &mut y, k = -1)"),
+            ("(1,)", "(1,)"),
+            ("(*r, true)", "(*r, true)"),
+            ("if a { 1 } else { ~b }", "if a { 1 } else { ~b }"),
+            ("while i < 3 { i = i + 1 }", "while (i < 3) { i = (i + 1) }"),
+            ("do {}", "do {}"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(tokenize(input)?)?.to_string(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_displays_uncallable_expression_in_error() -> Result<()> {
+        match parse(tokenize("(1 + 2)(3)")?) {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Parser error: Expected identifier to be called but found (1 + 2)"
+            ),
+            Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+        }
+        Ok(())
+    }
 }
@@ -1,9 +1,13 @@
-use std::rc::Rc;
+use std::{fmt, rc::Rc, str::FromStr};
 
 use crate::{
-    error::Result,
-    lexer::Token,
-    parser::{expression::Expression, statement::*},
+    error::{MovaError, Result},
+    lexer::{Span, Token, tokenize_with_spans},
+    parser::{
+        expression::Expression,
+        statement::*,
+        stream::{ParserOptions, TokenStream},
+    },
 };
 
 #[derive(Clone, Debug)]
@@ -12,13 +16,175 @@ pub enum Node {
     Statement(Rc<Statement>),
 }
 
-pub fn parse(mut tokens: Vec<Token>) -> Result<Node> {
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Expression(expression) => write!(f, "{expression}"),
+            Node::Statement(statement) => write!(f, "{statement}"),
+        }
+    }
+}
+
+/// A parsed program, so that source can be parsed with `str::parse`
+#[derive(Clone, Debug)]
+pub struct Program(pub Node);
+
+impl FromStr for Program {
+    type Err = MovaError;
+
+    fn from_str(source: &str) -> Result<Self> {
+        let tokens = tokenize_with_spans(source, false)?;
+        parse_with_spans(tokens, ParserOptions::default()).map(Program)
+    }
+}
+
+pub fn parse(tokens: Vec<Token>) -> Result<Node> {
+    parse_with(tokens, ParserOptions::default())
+}
+
+pub fn parse_with(tokens: Vec<Token>, options: ParserOptions) -> Result<Node> {
+    parse_stream(TokenStream::new(tokens, options))
+}
+
+/// Parses tokens paired with their source locations, so errors can point at a line
+pub fn parse_with_spans(tokens: Vec<(Token, Span)>, options: ParserOptions) -> Result<Node> {
+    parse_stream(TokenStream::with_spans(tokens, options))
+}
+
+/// Parses top-level statements one at a time instead of building the whole program
+pub fn parse_statements(tokens: Vec<(Token, Span)>, options: ParserOptions) -> Statements {
+    Statements {
+        tokens: TokenStream::with_spans(tokens, options),
+        failed: false,
+    }
+}
+
+/// Parses the whole program, moving on to the next statement after each error so that
+/// every error is reported at once
+pub fn parse_all(tokens: Vec<(Token, Span)>, options: ParserOptions) -> (Node, Vec<MovaError>) {
+    let mut tokens = TokenStream::with_spans(tokens, options);
     let mut body = Vec::new();
+    let mut errors = Vec::new();
 
-    tokens.reverse();
-    while tokens.len() != 0 {
-        body.push(parse_statement(&mut tokens)?);
+    while !tokens.is_at_end() {
+        match parse_statement(&mut tokens) {
+            Ok(node) => body.push(node),
+            Err(e) => {
+                errors.push(e);
+                tokens.recover();
+            }
+        }
     }
 
+    let program = Node::Expression(Rc::new(Expression::Program(body.into())));
+    (program, errors)
+}
+
+fn parse_stream(tokens: TokenStream) -> Result<Node> {
+    let statements = Statements {
+        tokens,
+        failed: false,
+    };
+    let body = statements.collect::<Result<Vec<Node>>>()?;
+
     Ok(Node::Expression(Rc::new(Expression::Program(body.into()))))
 }
+
+/// Top-level statements of a program, ending after the first error
+#[derive(Debug)]
+pub struct Statements {
+    tokens: TokenStream,
+    failed: bool,
+}
+
+impl Iterator for Statements {
+    type Item = Result<Node>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.tokens.is_at_end() {
+            return None;
+        }
+
+        let statement = parse_statement(&mut self.tokens);
+        self.failed = statement.is_err();
+        Some(statement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{LexerOptions, tokenize_with};
+
+    #[test]
+    fn it_parses_program_from_str() -> Result<()> {
+        let Program(Node::Expression(program)) = "let x = 1\nx + 2".parse()? else {
+            panic!("Expected program expression");
+        };
+        assert!(matches!(&*program, Expression::Program(body) if body.len() == 2));
+        Ok(())
+    }
+
+    #[test]
+    fn it_stops_statements_after_error() -> Result<()> {
+        let tokens = tokenize_with_spans("let x = 1\nlet = 2\nx", false)?;
+        let statements: Vec<Result<Node>> =
+            parse_statements(tokens, ParserOptions::default()).collect();
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].is_ok());
+        assert!(statements[1].is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_collects_every_statement_error() -> Result<()> {
+        let tokens = tokenize_with_spans("let = 1\nlet x = 2; fn (a) = a\nx = = 2\nx * 3", false)?;
+        let (program, errors) = parse_all(tokens, ParserOptions::default());
+
+        let lines: Vec<usize> = errors
+            .iter()
+            .filter_map(|e| e.position().map(|p| p.line))
+            .collect();
+        assert_eq!(lines, vec![1, 2, 3]);
+        let Node::Expression(program) = program else {
+            panic!("Expected program expression");
+        };
+        assert!(matches!(&*program, Expression::Program(body) if body.len() == 2));
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_errors_from_str() {
+        assert!("1 @ 2".parse::<Program>().is_err());
+        match "let = 1".parse::<Program>() {
+            Err(MovaError::Parser { .. }) => {}
+            result => panic!("Expected a parser error but got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn it_ends_statements_at_significant_newlines() -> Result<()> {
+        let options = LexerOptions {
+            significant_newlines: true,
+            ..LexerOptions::default()
+        };
+        let tokens = tokenize_with("let r = &total\n*r", options.clone())?;
+        let Node::Expression(program) = parse(tokens)? else {
+            panic!("Expected program expression");
+        };
+        let Expression::Program(body) = &*program else {
+            panic!("Expected program but got {program:?}");
+        };
+        assert_eq!(body.len(), 2);
+        assert!(
+            matches!(&body[1], Node::Expression(e) if matches!(**e, Expression::Dereference(_)))
+        );
+
+        // An operator at the end of a line still continues the expression
+        let Node::Expression(program) = parse(tokenize_with("1 +\n2", options)?)? else {
+            panic!("Expected program expression");
+        };
+        assert!(matches!(&*program, Expression::Program(body) if body.len() == 1));
+        Ok(())
+    }
+}
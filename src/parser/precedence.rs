@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+/// Binding powers the parser uses to group operators, where higher binds tighter
+#[derive(Clone, Debug)]
+pub struct PrecedenceTable {
+    infix: HashMap<String, (u8, u8)>,
+    postfix: HashMap<String, u8>,
+    prefix: u8,
+}
+
+impl Default for PrecedenceTable {
+    fn default() -> Self {
+        let infix = [
+            ("&&", (1, 2)),
+            ("==", (3, 4)),
+            ("!=", (3, 4)),
+            ("<", (3, 4)),
+            (">", (3, 4)),
+            ("|", (5, 6)),
+            ("^", (7, 8)),
+            ("&", (9, 10)),
+            ("<<", (11, 12)),
+            (">>", (11, 12)),
+            ("+", (13, 14)),
+            ("-", (13, 14)),
+            ("*", (15, 16)),
+            ("/", (15, 16)),
+            ("%", (15, 16)),
+        ];
+        let postfix = [("(", 18), ("[", 18), (".", 18)];
+
+        Self {
+            infix: infix.into_iter().map(|(o, p)| (o.to_string(), p)).collect(),
+            postfix: postfix
+                .into_iter()
+                .map(|(o, p)| (o.to_string(), p))
+                .collect(),
+            prefix: 17,
+        }
+    }
+}
+
+impl PrecedenceTable {
+    /// Sets the left and right binding power of an infix operator
+    pub fn with_infix(mut self, operator: &str, left: u8, right: u8) -> Self {
+        self.infix.insert(operator.into(), (left, right));
+        self
+    }
+
+    /// Sets the binding power of a postfix operator such as a call or an index
+    pub fn with_postfix(mut self, operator: &str, power: u8) -> Self {
+        self.postfix.insert(operator.into(), power);
+        self
+    }
+
+    /// Sets the binding power of the prefix `&`, `*`, `~` and `-` operators
+    pub fn with_prefix(mut self, power: u8) -> Self {
+        self.prefix = power;
+        self
+    }
+
+    pub fn infix(&self, operator: &str) -> Option<(u8, u8)> {
+        self.infix.get(operator).copied()
+    }
+
+    pub fn postfix(&self, operator: &str) -> Option<u8> {
+        self.postfix.get(operator).copied()
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+}
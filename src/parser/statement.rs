@@ -1,157 +1,361 @@
-use std::rc::Rc;
+use std::{fmt, rc::Rc};
 
 use crate::{
-    error::{MovaError, ParserError, Result},
-    lexer::Token,
-    parser::{expression::*, node::Node},
+    error::{ParserError, Result},
+    lexer::{Operator, Token},
+    parser::{expression::*, node::Node, stream::TokenStream},
 };
 
+/// The left-hand side of a destructuring `let`
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Identifier(Rc<String>),
+    Tuple(Rc<[Pattern]>),
+}
+
+/// A function parameter, optionally with a value used when the argument is omitted
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    pub name: Rc<String>,
+    pub default: Option<Rc<Expression>>,
+}
+
 #[derive(Clone, Debug)]
 pub enum Statement {
     Variable {
         name: Rc<String>,
-        value: Rc<Expression>,
+        /// `None` for a declaration like `let x` that is assigned later
+        value: Option<Rc<Expression>>,
         is_mutable: bool,
     },
     Assignment {
         name: Rc<String>,
         value: Rc<Expression>,
     },
+    /// `a = b = value`, assigning the value once to every name from right to left
+    ChainedAssignment {
+        names: Rc<[Rc<String>]>,
+        value: Rc<Expression>,
+    },
     DereferenceAssignment {
         target: Rc<Expression>,
         value: Rc<Expression>,
     },
+    /// `let (a, (b, c)) = value`, binding each element of a tuple
+    Destructure {
+        pattern: Pattern,
+        value: Rc<Expression>,
+        is_mutable: bool,
+    },
     Function {
         name: Rc<String>,
-        parameters: Rc<[String]>,
+        parameters: Rc<[Parameter]>,
         body: Rc<Expression>,
+        /// The `///` comment before the declaration, if comments were preserved
+        docs: Option<Rc<str>>,
     },
 }
 
-fn parse_variable(tokens: &mut Vec<Token>) -> Result<Node> {
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Identifier(name) => write!(f, "{name}"),
+            Pattern::Tuple(patterns) => {
+                let patterns: Vec<String> = patterns.iter().map(Pattern::to_string).collect();
+                match patterns.as_slice() {
+                    [pattern] => write!(f, "({pattern},)"),
+                    patterns => write!(f, "({})", patterns.join(", ")),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let binding = |is_mutable: bool| if is_mutable { "let mut" } else { "let" };
+        match self {
+            Statement::Variable {
+                name,
+                value: Some(value),
+                is_mutable,
+            } => write!(f, "{} {name} = {value}", binding(*is_mutable)),
+            Statement::Variable {
+                name,
+                value: None,
+                is_mutable,
+            } => write!(f, "{} {name}", binding(*is_mutable)),
+            Statement::Assignment { name, value } => write!(f, "{name} = {value}"),
+            Statement::ChainedAssignment { names, value } => {
+                names.iter().try_for_each(|name| write!(f, "{name} = "))?;
+                write!(f, "{value}")
+            }
+            Statement::DereferenceAssignment { target, value } => write!(f, "*{target} = {value}"),
+            Statement::Destructure {
+                pattern,
+                value,
+                is_mutable,
+            } => write!(f, "{} {pattern} = {value}", binding(*is_mutable)),
+            Statement::Function {
+                name,
+                parameters,
+                body,
+                ..
+            } => {
+                let parameters: Vec<String> = parameters
+                    .iter()
+                    .map(|p| match &p.default {
+                        Some(default) => format!("{} = {default}", p.name),
+                        None => p.name.to_string(),
+                    })
+                    .collect();
+                write!(f, "fn {name}({}) = {body}", parameters.join(", "))
+            }
+        }
+    }
+}
+
+fn parse_variable(tokens: &mut TokenStream) -> Result<Node> {
     tokens.pop();
 
-    let is_mutable = matches!(tokens.last(), Some(Token::Keyword(k)) if k == "mut");
+    let is_mutable = matches!(tokens.last(), Token::Keyword(k) if k == "mut");
     if is_mutable {
         tokens.pop();
     }
 
-    let name = Rc::new(match tokens.pop() {
-        Some(Token::Identifier(i)) => i,
-        Some(t) => {
-            return Err(MovaError::Parser(ParserError::ExpectedIdentifierButGot(format!("{t:?}"))));
+    if let Token::Operator(Operator::LParen) = tokens.last() {
+        return parse_destructure(tokens, is_mutable);
+    }
+
+    let name = match tokens.pop() {
+        Token::Identifier(i) => tokens.intern(i),
+        Token::Keyword(k) => {
+            return Err(tokens.error_at_previous(ParserError::ReservedWordAsIdentifier(k)));
+        }
+        Token::Eof => {
+            return Err(tokens.error(ParserError::ExpectedIdentifierAfterLet));
         }
-        None => {
-            return Err(MovaError::Parser(
-                ParserError::ExpectedIdentifierAfterLet,
-            ));
+        t => {
+            return Err(tokens.error_at_previous(ParserError::UnexpectedToken {
+                found: Box::new(t),
+                expected: "an identifier",
+            }));
         }
-    });
+    };
 
+    let value = match tokens.last() {
+        Token::Assignment => {
+            tokens.pop();
+            Some(Rc::new(parse_expression(tokens)?))
+        }
+        _ => None,
+    };
+
+    Ok(Node::Statement(Rc::new(Statement::Variable {
+        name,
+        value,
+        is_mutable,
+    })))
+}
+
+fn parse_pattern(tokens: &mut TokenStream) -> Result<Pattern> {
     match tokens.pop() {
-        Some(Token::Assignment) => {
-            let value = Rc::new(parse_expression(tokens)?);
-            Ok(Node::Statement(Rc::new(Statement::Variable {
-                name,
-                value,
-                is_mutable,
-            })))
+        Token::Identifier(i) => Ok(Pattern::Identifier(tokens.intern(i))),
+        // As with expressions, `(a)` only groups, while `(a,)` is a tuple of one
+        Token::Operator(Operator::LParen) => tokens.nested(|tokens| {
+            let first = parse_pattern(tokens)?;
+            let mut elements = Vec::new();
+            loop {
+                match tokens.pop() {
+                    Token::SpecialCharacter(',') => {
+                        if elements.is_empty() {
+                            elements.push(first.clone());
+                        }
+                        if !matches!(tokens.last(), Token::Operator(Operator::RParen)) {
+                            elements.push(parse_pattern(tokens)?);
+                        }
+                    }
+                    Token::Operator(Operator::RParen) if elements.is_empty() => return Ok(first),
+                    Token::Operator(Operator::RParen) => break,
+                    Token::Eof => return Err(tokens.error(ParserError::UnexpectedEndOfInput)),
+                    t => {
+                        return Err(tokens.error_at_previous(ParserError::UnexpectedToken {
+                            found: Box::new(t),
+                            expected: "',' or ')' in tuple pattern",
+                        }));
+                    }
+                }
+            }
+            Ok(Pattern::Tuple(elements.into()))
+        }),
+        Token::Keyword(k) => {
+            Err(tokens.error_at_previous(ParserError::ReservedWordAsIdentifier(k)))
         }
-        Some(t) => Err(MovaError::Parser(ParserError::UnexpectedToken(format!("{t:?}")))),
-        None => Err(MovaError::Parser(
-            ParserError::ExpectedAssignmentAfterIdentifier,
-        )),
+        Token::Eof => Err(tokens.error(ParserError::UnexpectedEndOfInput)),
+        t => Err(tokens.error_at_previous(ParserError::UnexpectedToken {
+            found: Box::new(t),
+            expected: "an identifier",
+        })),
     }
 }
 
-fn parse_function(tokens: &mut Vec<Token>) -> Result<Node> {
+fn parse_destructure(tokens: &mut TokenStream, is_mutable: bool) -> Result<Node> {
+    let pattern = parse_pattern(tokens)?;
+
+    match tokens.pop() {
+        Token::Assignment => {}
+        Token::Eof => return Err(tokens.error(ParserError::UnexpectedEndOfInput)),
+        t => {
+            return Err(tokens.error_at_previous(ParserError::UnexpectedToken {
+                found: Box::new(t),
+                expected: "'='",
+            }));
+        }
+    }
+    let value = Rc::new(parse_expression(tokens)?);
+
+    Ok(Node::Statement(Rc::new(Statement::Destructure {
+        pattern,
+        value,
+        is_mutable,
+    })))
+}
+
+/// Parses the optional `= default` after a parameter name.
+/// Once one parameter has a default, every later one needs a default too.
+fn parse_parameter(
+    tokens: &mut TokenStream,
+    name: Rc<String>,
+    previous: &[Parameter],
+) -> Result<Parameter> {
+    let default = match tokens.last() {
+        Token::Assignment => {
+            tokens.pop();
+            Some(Rc::new(parse_expression(tokens)?))
+        }
+        _ => None,
+    };
+
+    if default.is_none() && previous.iter().any(|p| p.default.is_some()) {
+        return Err(
+            tokens.error_at_previous(ParserError::ExpectedDefaultForParameter(name.to_string()))
+        );
+    }
+
+    Ok(Parameter { name, default })
+}
+
+fn parse_function(tokens: &mut TokenStream) -> Result<Node> {
+    let docs = tokens.doc();
     tokens.pop();
 
-    let name = Rc::new(match tokens.pop() {
-        Some(Token::Identifier(i)) => i,
+    let name = match tokens.pop() {
+        Token::Identifier(i) => tokens.intern(i),
+        Token::Keyword(k) => {
+            return Err(tokens.error_at_previous(ParserError::ReservedWordAsIdentifier(k)));
+        }
         _ => {
-            return Err(MovaError::Parser(
-                ParserError::ExpectedFunctionName,
-            ));
+            return Err(tokens.error_at_previous(ParserError::ExpectedFunctionName));
         }
-    });
+    };
     match tokens.pop() {
-        Some(Token::Operator(o)) if o == "(" => {}
+        Token::Operator(Operator::LParen) => {}
         _ => {
-            return Err(MovaError::Parser(
-                ParserError::ExpectedParameterList,
-            ));
+            return Err(tokens.error_at_previous(ParserError::ExpectedParameterList));
         }
     }
 
     let mut parameters = Vec::new();
     loop {
-        match tokens.last() {
-            Some(token) => match token {
-                Token::Operator(o) if o == ")" => break,
-                _ => {
-                    if let Some(t) = tokens.pop() {
-                        if let Token::Identifier(i) = t {
-                            parameters.push(i);
-                        }
-                    }
-                }
-            },
-            None => {
-                return Err(MovaError::Parser(
-                    ParserError::ExpectedParameterListToBeClosed,
-                ));
+        match tokens.pop() {
+            Token::Operator(Operator::RParen) => break,
+            Token::Identifier(_) if parameters.len() == tokens.max_arguments() => {
+                return Err(tokens
+                    .error_at_previous(ParserError::TooManyParameters(tokens.max_arguments())));
+            }
+            Token::Identifier(name) => {
+                let name = tokens.intern(name);
+                parameters.push(parse_parameter(tokens, name, &parameters)?);
+            }
+            Token::Keyword(k) => {
+                return Err(tokens.error_at_previous(ParserError::ReservedWordAsIdentifier(k)));
             }
+            Token::Eof => {
+                return Err(tokens.error(ParserError::ExpectedParameterListToBeClosed));
+            }
+            _ => {}
         }
     }
 
     match tokens.pop() {
-        Some(Token::Operator(o)) if o == ")" => {}
-        _ => {
-            return Err(MovaError::Parser(
-                ParserError::ExpectedParameterListToBeClosed,
-            ));
-        }
+        Token::Assignment => {}
+        _ => Err(tokens.error_at_previous(ParserError::ExpectedAssignmentBeforeFunctionBody))?,
     }
 
-    match tokens.pop() {
-        Some(Token::Assignment) => {}
-        _ => Err(MovaError::Parser(
-            ParserError::ExpectedAssignmentBeforeFunctionBody,
-        ))?,
+    // Statements only form a body when wrapped in a block, as in `fn f(x) = { let y = x }`
+    if matches!(tokens.last(), Token::Keyword(k) if k == "let" || k == "fn") {
+        return Err(tokens.error(ParserError::FunctionBodyMustBeExpressionOrBlock));
     }
 
     Ok(Node::Statement(Rc::new(Statement::Function {
         name,
         parameters: parameters.into(),
         body: Rc::new(parse_expression(tokens)?),
+        docs,
     })))
 }
 
-pub fn parse_statement(tokens: &mut Vec<Token>) -> Result<Node> {
-    while let Some(Token::SpecialCharacter(';')) = tokens.last() {
+/// Parses the value of `name = ...`, which may itself assign to further names as in
+/// `a = b = 1`
+fn parse_assignment(tokens: &mut TokenStream, name: Rc<String>) -> Result<Node> {
+    let mut names = vec![name];
+    loop {
+        let value = parse_expression(tokens)?;
+        if !matches!(tokens.last(), Token::Assignment) {
+            let statement = match names.as_slice() {
+                [name] => Statement::Assignment {
+                    name: Rc::clone(name),
+                    value: Rc::new(value),
+                },
+                _ => Statement::ChainedAssignment {
+                    names: names.into(),
+                    value: Rc::new(value),
+                },
+            };
+            return Ok(Node::Statement(Rc::new(statement)));
+        }
+
+        tokens.pop();
+        match value {
+            Expression::Identifier(name) => names.push(name),
+            e => {
+                return Err(tokens.error(ParserError::InvalidAssignmentTarget(e.to_string())));
+            }
+        }
+    }
+}
+
+pub fn parse_statement(tokens: &mut TokenStream) -> Result<Node> {
+    while let Token::SpecialCharacter(';') = tokens.last() {
         tokens.pop();
     }
 
     let node = match tokens.last() {
-        Some(Token::Keyword(k)) if k == "let" => parse_variable(tokens),
-        Some(Token::Keyword(k)) if k == "fn" => parse_function(tokens),
-        Some(_) => {
+        Token::Keyword(k) if k == "let" => parse_variable(tokens),
+        Token::Keyword(k) if k == "fn" => parse_function(tokens),
+        Token::Eof => Err(tokens.error(ParserError::UnexpectedEndOfInput)),
+        _ => {
             let result = parse_expression(tokens);
             match result? {
                 Expression::Identifier(name) => match tokens.last() {
-                    Some(Token::Assignment) => {
+                    Token::Assignment => {
                         tokens.pop();
-                        let value = parse_expression(tokens)?;
-                        Ok(Node::Statement(Rc::new(Statement::Assignment {
-                            name,
-                            value: Rc::new(value),
-                        })))
+                        parse_assignment(tokens, name)
                     }
                     _ => Ok(Node::Expression(Rc::new(Expression::Identifier(name)))),
                 },
                 Expression::Dereference(target) => match tokens.last() {
-                    Some(Token::Assignment) => {
+                    Token::Assignment => {
                         tokens.pop();
                         let value = parse_expression(tokens)?;
                         Ok(Node::Statement(Rc::new(Statement::DereferenceAssignment {
@@ -161,15 +365,264 @@ pub fn parse_statement(tokens: &mut Vec<Token>) -> Result<Node> {
                     }
                     _ => Ok(Node::Expression(Rc::new(Expression::Dereference(target)))),
                 },
+                e if matches!(tokens.last(), Token::Assignment) => {
+                    Err(tokens.error(ParserError::InvalidAssignmentTarget(e.to_string())))
+                }
                 e => Ok(Node::Expression(Rc::new(e))),
             }
         }
-        None => Err(MovaError::Parser(ParserError::UnexpectedEndOfInput)),
     }?;
 
-    while let Some(Token::SpecialCharacter(';')) = tokens.last() {
+    while let Token::SpecialCharacter(';') = tokens.last() {
         tokens.pop();
     }
 
     Ok(node)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::MovaError, lexer::tokenize, parser::parse};
+
+    fn first_statement(input: &str) -> Result<Rc<Statement>> {
+        let Node::Expression(program) = parse(tokenize(input)?)? else {
+            panic!("Expected program expression");
+        };
+        match &*program {
+            Expression::Program(body) => match &body[0] {
+                Node::Statement(statement) => Ok(Rc::clone(statement)),
+                n => panic!("Expected statement but got {n:?}"),
+            },
+            e => panic!("Expected program but got {e:?}"),
+        }
+    }
+
+    #[test]
+    fn it_parses_tuple_destructure() -> Result<()> {
+        match &*first_statement("let mut (a, b) = f(1)")? {
+            Statement::Destructure {
+                pattern: Pattern::Tuple(elements),
+                value,
+                is_mutable: true,
+            } => {
+                let names: Vec<String> = elements
+                    .iter()
+                    .map(|element| match element {
+                        Pattern::Identifier(name) => name.to_string(),
+                        p => panic!("Expected identifier but got {p:?}"),
+                    })
+                    .collect();
+                assert_eq!(names, ["a", "b"]);
+                assert!(matches!(**value, Expression::Call { .. }));
+            }
+            s => panic!("Expected destructure but got {s:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_nested_pattern() -> Result<()> {
+        match &*first_statement("let (a, (b, c)) = t")? {
+            Statement::Destructure {
+                pattern: Pattern::Tuple(elements),
+                ..
+            } => {
+                assert!(matches!(&elements[0], Pattern::Identifier(_)));
+                assert!(matches!(&elements[1], Pattern::Tuple(inner) if inner.len() == 2));
+            }
+            s => panic!("Expected destructure but got {s:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_parenthesized_name_as_identifier_pattern() -> Result<()> {
+        match &*first_statement("let (a) = t")? {
+            Statement::Destructure {
+                pattern: Pattern::Identifier(name),
+                ..
+            } => assert_eq!(name.as_str(), "a"),
+            s => panic!("Expected identifier pattern but got {s:?}"),
+        }
+
+        for (input, length) in [("let ((a), b,) = t", 2), ("let (a,) = t", 1)] {
+            match &*first_statement(input)? {
+                Statement::Destructure {
+                    pattern: Pattern::Tuple(elements),
+                    ..
+                } => {
+                    assert_eq!(elements.len(), length);
+                    assert!(matches!(&elements[0], Pattern::Identifier(_)));
+                }
+                s => panic!("Expected tuple pattern but got {s:?}"),
+            }
+        }
+        assert_eq!(first_statement("let (a,) = t")?.to_string(), "let (a,) = t");
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_unclosed_pattern() -> Result<()> {
+        match parse(tokenize("let (a b) = t")?) {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Parser error: Expected ',' or ')' in tuple pattern but found identifier 'b'"
+            ),
+            Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_default_parameters() -> Result<()> {
+        match &*first_statement("fn greet(name, greeting = 1 + 2, times = 3) = name")? {
+            Statement::Function { parameters, .. } => {
+                let names: Vec<&str> = parameters.iter().map(|p| p.name.as_str()).collect();
+                assert_eq!(names, ["name", "greeting", "times"]);
+                assert!(parameters[0].default.is_none());
+                assert!(matches!(
+                    parameters[1].default.as_deref(),
+                    Some(Expression::BinaryExpression { .. })
+                ));
+                assert!(matches!(
+                    parameters[2].default.as_deref(),
+                    Some(Expression::Number(3))
+                ));
+            }
+            s => panic!("Expected function but got {s:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_required_parameter_after_default() -> Result<()> {
+        match parse(tokenize("fn f(a = 1, b) = a")?) {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Parser error: Expected a default value for parameter 'b', since an earlier parameter has one"
+            ),
+            Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_statement_as_function_body() -> Result<()> {
+        for input in ["fn f(x) = let y = 1", "fn f(x) = fn g() = 1"] {
+            match parse(tokenize(input)?) {
+                Err(e) => assert_eq!(
+                    e.to_string(),
+                    "Parser error: Function body must be an expression or block"
+                ),
+                Ok(node) => panic!("Expected an error but parsed: {node:?}"),
+            }
+        }
+        parse(tokenize("fn f(x) = { let y = x; y }")?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_reserved_word_as_name() -> Result<()> {
+        let cases = [
+            ("let if = 1", "if"),
+            ("let mut fn = 1", "fn"),
+            ("let (a, while) = (1, 2)", "while"),
+            ("fn do() = 1", "do"),
+            ("fn f(a, else) = a", "else"),
+        ];
+        for (input, keyword) in cases {
+            match parse(tokenize(input)?) {
+                Err(MovaError::Parser {
+                    error: ParserError::ReservedWordAsIdentifier(k),
+                    ..
+                }) => {
+                    assert_eq!(k, keyword, "{input}")
+                }
+                result => panic!("Expected a reserved word error for {input} but got {result:?}"),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_displays_statements_as_source() -> Result<()> {
+        let input = "let mut (a, (b, c)) = t; let d; fn g(x, y = 2) = do x; *r = 1";
+        assert_eq!(
+            parse(tokenize(input)?)?.to_string(),
+            "let mut (a, (b, c)) = t; let d; fn g(x, y = 2) = do x; *r = 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_interns_declared_names() -> Result<()> {
+        let Statement::Function {
+            name,
+            parameters,
+            body,
+            ..
+        } = &*first_statement("fn a(a, b = a) = { let b = a; b }")?
+        else {
+            panic!("Expected function declaration");
+        };
+        let Some(default) = &parameters[1].default else {
+            panic!("Expected a default for 'b'");
+        };
+        let Expression::Identifier(default) = &**default else {
+            panic!("Expected identifier but got {default:?}");
+        };
+        assert!(Rc::ptr_eq(name, &parameters[0].name));
+        assert!(Rc::ptr_eq(name, default));
+
+        let Expression::Block(block) = &**body else {
+            panic!("Expected block but got {body:?}");
+        };
+        match (&block[0], &block[1]) {
+            (Node::Statement(statement), Node::Expression(result)) => {
+                let (Statement::Variable { name: b, .. }, Expression::Identifier(used)) =
+                    (&**statement, &**result)
+                else {
+                    panic!("Expected declaration and use but got {block:?}");
+                };
+                assert!(Rc::ptr_eq(b, &parameters[1].name));
+                assert!(Rc::ptr_eq(b, used));
+            }
+            n => panic!("Expected declaration and use but got {n:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_chained_assignment() -> Result<()> {
+        match &*first_statement("a = b = c = 1")? {
+            Statement::ChainedAssignment { names, value } => {
+                let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+                assert_eq!(names, ["a", "b", "c"]);
+                assert!(matches!(**value, Expression::Number(1)));
+            }
+            s => panic!("Expected chained assignment but got {s:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_assignment_to_non_lvalue() -> Result<()> {
+        for (input, target) in [
+            ("1 = x", "1"),
+            ("a = 1 = 2", "1"),
+            ("(a + b) = 1", "(a + b)"),
+        ] {
+            match parse(tokenize(input)?) {
+                Err(MovaError::Parser {
+                    error: ParserError::InvalidAssignmentTarget(t),
+                    ..
+                }) => {
+                    assert_eq!(t, target, "{input}")
+                }
+                result => panic!("Expected an invalid target error for {input} but got {result:?}"),
+            }
+        }
+        Ok(())
+    }
+}
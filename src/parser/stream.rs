@@ -0,0 +1,342 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    error::{MovaError, ParserError, Position, Result},
+    lexer::{Span, Token},
+    parser::precedence::PrecedenceTable,
+};
+
+#[derive(Debug, Clone)]
+pub struct ParserOptions {
+    /// How deeply blocks and expressions may nest before parsing is aborted
+    pub max_depth: usize,
+    /// How many parameters a function may declare and how many arguments a call may pass
+    pub max_arguments: usize,
+    pub precedence: PrecedenceTable,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 256,
+            max_arguments: 255,
+            precedence: PrecedenceTable::default(),
+        }
+    }
+}
+
+fn located(error: ParserError, span: Option<Span>) -> MovaError {
+    MovaError::Parser {
+        error,
+        position: span.map(|span| Position {
+            line: span.line,
+            character: span.column,
+        }),
+    }
+}
+
+/// A token, its location if known, and whether a `Token::Newline` came right before it
+type Entry = (Token, Option<Span>, bool);
+
+#[derive(Debug)]
+pub struct TokenStream {
+    tokens: Vec<Entry>,
+    options: ParserOptions,
+    depth: usize,
+    /// Location of the most recently popped token
+    previous: Option<Span>,
+    /// Names seen so far, shared by every occurrence of the same identifier
+    names: HashSet<Rc<String>>,
+    /// Doc comments by the index in `tokens` of the token they precede
+    docs: HashMap<usize, Rc<str>>,
+}
+
+impl TokenStream {
+    pub fn new(tokens: Vec<Token>, options: ParserOptions) -> Self {
+        Self::from_entries(tokens.into_iter().map(|t| (t, None)).collect(), options)
+    }
+
+    pub fn with_spans(tokens: Vec<(Token, Span)>, options: ParserOptions) -> Self {
+        Self::from_entries(
+            tokens.into_iter().map(|(t, s)| (t, Some(s))).collect(),
+            options,
+        )
+    }
+
+    fn from_entries(entries: Vec<(Token, Option<Span>)>, options: ParserOptions) -> Self {
+        // Newlines only separate statements and doc comments only describe a declaration,
+        // so both are folded into the token that follows, while other comments are dropped
+        let mut tokens = Vec::with_capacity(entries.len());
+        let mut docs = Vec::new();
+        let mut after_newline = false;
+        let mut doc: Option<String> = None;
+        for (token, span) in entries {
+            match token {
+                Token::Newline => after_newline = true,
+                Token::Comment(_) => {}
+                Token::DocComment(line) => match &mut doc {
+                    Some(doc) => {
+                        doc.push('\n');
+                        doc.push_str(&line);
+                    }
+                    None => doc = Some(line),
+                },
+                token => {
+                    if let Some(doc) = doc.take() {
+                        docs.push((tokens.len(), Rc::from(doc)));
+                    }
+                    tokens.push((token, span, after_newline));
+                    after_newline = false;
+                }
+            }
+        }
+
+        // End the stream with `Token::Eof`, located right after the last token, so the
+        // parser always has a next token to look at
+        let end = tokens
+            .last()
+            .and_then(|(_, span, _)| *span)
+            .map(|span: Span| Span {
+                start: span.end,
+                end: span.end,
+                line: span.line,
+                column: span.end_column,
+                end_column: span.end_column,
+            });
+        tokens.push((Token::Eof, end, after_newline));
+
+        // Tokens are consumed from the end, so keep them in reverse order
+        tokens.reverse();
+        let last = tokens.len().saturating_sub(1);
+        Self {
+            docs: docs
+                .into_iter()
+                .map(|(index, doc)| (last - index, doc))
+                .collect(),
+            tokens,
+            options,
+            depth: 0,
+            previous: None,
+            names: HashSet::new(),
+        }
+    }
+
+    /// The next token, which is `Token::Eof` once the input is used up
+    pub fn last(&self) -> &Token {
+        self.tokens.last().map_or(&Token::Eof, |(token, ..)| token)
+    }
+
+    /// The token after the next one
+    pub fn second(&self) -> &Token {
+        self.tokens
+            .iter()
+            .rev()
+            .nth(1)
+            .map_or(&Token::Eof, |(token, ..)| token)
+    }
+
+    /// Location of the next token, if the stream was built with spans
+    pub fn span(&self) -> Option<Span> {
+        self.tokens.last().and_then(|(_, span, _)| *span)
+    }
+
+    /// Consumes the next token, except for the final `Token::Eof`, which is returned every time
+    pub fn pop(&mut self) -> Token {
+        let Some((token, span, _)) = self.tokens.pop_if(|(token, ..)| *token != Token::Eof) else {
+            return Token::Eof;
+        };
+        self.previous = span;
+        token
+    }
+
+    /// Locates an error at the next token, which is the final `Token::Eof` once the input is
+    /// used up
+    pub fn error(&self, error: ParserError) -> MovaError {
+        located(error, self.span())
+    }
+
+    /// Locates an error at the token consumed last, for when that token was the one rejected
+    pub fn error_at_previous(&self, error: ParserError) -> MovaError {
+        located(error, self.previous)
+    }
+
+    /// Whether the next token is on a later line than the previous one, if spans are known
+    pub fn starts_line(&self) -> bool {
+        match (self.previous, self.span()) {
+            (Some(previous), Some(next)) => next.line > previous.line,
+            _ => self.after_newline(),
+        }
+    }
+
+    /// Whether a `Token::Newline` separates the next token from the previous one,
+    /// which ends the statement before it
+    pub fn after_newline(&self) -> bool {
+        self.tokens
+            .last()
+            .is_some_and(|(.., after_newline)| *after_newline)
+    }
+
+    /// The doc comment written right before the next token
+    pub fn doc(&self) -> Option<Rc<str>> {
+        let index = self.tokens.len().checked_sub(1)?;
+        self.docs.get(&index).cloned()
+    }
+
+    /// Returns the shared `Rc` for `name`, allocating one on its first occurrence
+    pub fn intern(&mut self, name: String) -> Rc<String> {
+        if let Some(shared) = self.names.get(&name) {
+            return Rc::clone(shared);
+        }
+        let shared = Rc::new(name);
+        self.names.insert(Rc::clone(&shared));
+        shared
+    }
+
+    pub fn max_arguments(&self) -> usize {
+        self.options.max_arguments
+    }
+
+    pub fn precedence(&self) -> &PrecedenceTable {
+        &self.options.precedence
+    }
+
+    /// Whether only `Token::Eof` is left
+    pub fn is_at_end(&self) -> bool {
+        *self.last() == Token::Eof
+    }
+
+    /// Skips past the statement an error occurred in, up to a `;`, a token starting a new line
+    /// or the end of input. At least one token is consumed, so parsing always moves on.
+    pub fn recover(&mut self) {
+        if let Token::SpecialCharacter(';') = self.pop() {
+            return;
+        }
+        while !self.is_at_end() && !self.starts_line() {
+            if let Token::SpecialCharacter(';') = self.pop() {
+                return;
+            }
+        }
+    }
+
+    /// Runs a recursive parsing step one nesting level deeper
+    pub fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.options.max_depth {
+            return Err(self.error(ParserError::MaximumNestingDepthExceeded));
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::{MovaError, ParserError, Result},
+        lexer::{Token, tokenize, tokenize_with_spans},
+        parser::{ParserOptions, parse, parse_with},
+    };
+
+    use super::TokenStream;
+
+    #[test]
+    fn it_ends_with_eof_after_the_last_token() -> Result<()> {
+        let tokens = tokenize_with_spans("x\n  y", false)?;
+        let mut tokens = TokenStream::with_spans(tokens, ParserOptions::default());
+        tokens.pop();
+        tokens.pop();
+        assert!(tokens.is_at_end());
+        assert_eq!(
+            tokens.span().map(|span| (span.start, span.line)),
+            Some((5, 2))
+        );
+
+        // The sentinel is never consumed
+        assert_eq!(tokens.pop(), Token::Eof);
+        assert_eq!(*tokens.last(), Token::Eof);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_deeply_nested_parentheses() -> Result<()> {
+        let input = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        let result = parse(tokenize(&input)?);
+        assert!(matches!(
+            result,
+            Err(MovaError::Parser {
+                error: ParserError::MaximumNestingDepthExceeded,
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_deeply_nested_blocks() -> Result<()> {
+        let input = format!("{}1{}", "{".repeat(100_000), "}".repeat(100_000));
+        let result = parse(tokenize(&input)?);
+        assert!(matches!(
+            result,
+            Err(MovaError::Parser {
+                error: ParserError::MaximumNestingDepthExceeded,
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_nesting_within_the_limit() -> Result<()> {
+        let input = format!("{}1{}", "({".repeat(120), "})".repeat(120));
+        parse(tokenize(&input)?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_more_arguments_than_configured() -> Result<()> {
+        let options = ParserOptions {
+            max_arguments: 2,
+            ..ParserOptions::default()
+        };
+        parse_with(tokenize("f(1, 2)")?, options.clone())?;
+        let result = parse_with(tokenize("f(1, 2, x = 3)")?, options.clone());
+        assert!(matches!(
+            result,
+            Err(MovaError::Parser {
+                error: ParserError::TooManyArguments(2),
+                ..
+            })
+        ));
+        let result = parse_with(tokenize("fn f(a, b, c) = a")?, options);
+        assert!(matches!(
+            result,
+            Err(MovaError::Parser {
+                error: ParserError::TooManyParameters(2),
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn it_respects_configured_depth() -> Result<()> {
+        let options = ParserOptions {
+            max_depth: 4,
+            ..ParserOptions::default()
+        };
+        let result = parse_with(tokenize("((((1))))")?, options);
+        assert!(matches!(
+            result,
+            Err(MovaError::Parser {
+                error: ParserError::MaximumNestingDepthExceeded,
+                ..
+            })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use std::rc::Rc;
+
+use crate::{
+    error::MovaWarning,
+    parser::{expression::Expression, node::Node, statement::Statement},
+};
+
+/// Traverses the syntax tree, recursing into children unless a method is overridden
+///
+/// An overriding method can call the matching `walk_*` function to keep descending.
+pub trait Visitor {
+    fn visit_node(&mut self, node: &Node) {
+        walk_node(self, node);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+    match node {
+        Node::Expression(expression) => visitor.visit_expression(expression),
+        Node::Statement(statement) => visitor.visit_statement(statement),
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression {
+        Expression::Number(_) | Expression::Boolean(_) | Expression::Identifier(_) => {}
+        Expression::Reference { data, .. } => visitor.visit_expression(data),
+        Expression::BinaryExpression { left, right, .. } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        Expression::Call { arguments, .. } => {
+            arguments
+                .positional
+                .iter()
+                .for_each(|argument| visitor.visit_expression(argument));
+            arguments
+                .named
+                .iter()
+                .for_each(|argument| visitor.visit_expression(&argument.value));
+        }
+        Expression::Index { target, index } => {
+            visitor.visit_expression(target);
+            visitor.visit_expression(index);
+        }
+        Expression::Dereference(inner)
+        | Expression::BitwiseNot(inner)
+        | Expression::Negate(inner)
+        | Expression::Do(inner) => {
+            visitor.visit_expression(inner);
+        }
+        Expression::Tuple(elements) => {
+            elements
+                .iter()
+                .for_each(|element| visitor.visit_expression(element));
+        }
+        Expression::Block(body) | Expression::Program(body) => {
+            body.iter().for_each(|node| visitor.visit_node(node));
+        }
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expression(condition);
+            visitor.visit_expression(consequence);
+            if let Some(alternative) = alternative {
+                visitor.visit_expression(alternative);
+            }
+        }
+        Expression::While { condition, body } => {
+            visitor.visit_expression(condition);
+            visitor.visit_expression(body);
+        }
+        Expression::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expression(value);
+            }
+        }
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::Variable { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expression(value);
+            }
+        }
+        Statement::Assignment { value, .. }
+        | Statement::ChainedAssignment { value, .. }
+        | Statement::Destructure { value, .. } => {
+            visitor.visit_expression(value);
+        }
+        Statement::DereferenceAssignment { target, value } => {
+            visitor.visit_expression(target);
+            visitor.visit_expression(value);
+        }
+        Statement::Function {
+            parameters, body, ..
+        } => {
+            for default in parameters.iter().filter_map(|p| p.default.as_ref()) {
+                visitor.visit_expression(default);
+            }
+            visitor.visit_expression(body);
+        }
+    }
+}
+
+/// Collects the doc comments of documented functions
+#[derive(Default)]
+struct DocCollector {
+    docs: Vec<(Rc<String>, Rc<str>)>,
+}
+
+impl Visitor for DocCollector {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let Statement::Function {
+            name,
+            docs: Some(docs),
+            ..
+        } = statement
+        {
+            self.docs.push((Rc::clone(name), Rc::clone(docs)));
+        }
+        walk_statement(self, statement);
+    }
+}
+
+/// Names and doc comments of the documented functions in the tree, in source order
+pub fn docs(node: &Node) -> Vec<(Rc<String>, Rc<str>)> {
+    let mut collector = DocCollector::default();
+    collector.visit_node(node);
+    collector.docs
+}
+
+/// Flags the first node of a block that follows a node which always returns
+#[derive(Default)]
+struct UnreachableCode {
+    warnings: Vec<MovaWarning>,
+}
+
+impl Visitor for UnreachableCode {
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Expression::Block(body) | Expression::Program(body) = expression
+            && let Some(position) = body.iter().position(node_always_returns)
+            && let Some(unreachable) = body.get(position + 1)
+        {
+            self.warnings
+                .push(MovaWarning::UnreachableCode(unreachable.to_string()));
+        }
+        walk_expression(self, expression);
+    }
+}
+
+fn node_always_returns(node: &Node) -> bool {
+    match node {
+        Node::Expression(expression) => always_returns(expression),
+        Node::Statement(statement) => match &**statement {
+            Statement::Variable {
+                value: Some(value), ..
+            }
+            | Statement::Assignment { value, .. }
+            | Statement::ChainedAssignment { value, .. }
+            | Statement::Destructure { value, .. } => always_returns(value),
+            _ => false,
+        },
+    }
+}
+
+/// Whether evaluating the expression leaves the function on every path, where an `if`
+/// only does so when both of its branches do
+fn always_returns(expression: &Expression) -> bool {
+    match expression {
+        Expression::Return(_) => true,
+        Expression::Block(body) => body.iter().any(node_always_returns),
+        Expression::Do(inner) => always_returns(inner),
+        Expression::If {
+            consequence,
+            alternative: Some(alternative),
+            ..
+        } => always_returns(consequence) && always_returns(alternative),
+        _ => false,
+    }
+}
+
+/// Warnings for code that can never run because it follows a `return` in the same block
+pub fn unreachable_code(node: &Node) -> Vec<MovaWarning> {
+    let mut lint = UnreachableCode::default();
+    lint.visit_node(node);
+    lint.warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::Result,
+        lexer::{tokenize, tokenize_with_spans},
+        parser::{ParserOptions, parse, parse_with_spans},
+    };
+
+    #[derive(Default)]
+    struct CallCounter {
+        calls: usize,
+    }
+
+    impl Visitor for CallCounter {
+        fn visit_expression(&mut self, expression: &Expression) {
+            if let Expression::Call { .. } = expression {
+                self.calls += 1;
+            }
+            walk_expression(self, expression);
+        }
+    }
+
+    #[test]
+    fn it_counts_calls() -> Result<()> {
+        let input = "
+            fn add(a, b) = a + b
+            fn twice(x) = { let y = add(x, x) y }
+            let mut n = twice(add(1, 2))
+            while n < 10 { n = add(n, 1) }
+            if n == 10 { drop(n) }
+        ";
+        let program = parse(tokenize(input)?)?;
+
+        let mut counter = CallCounter::default();
+        counter.visit_node(&program);
+        assert_eq!(counter.calls, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn it_warns_once_about_code_after_return() -> Result<()> {
+        let input = "
+            fn f(x) = {
+                return x
+                let y = x + 1
+                y
+            }
+        ";
+        let warnings = unreachable_code(&parse(tokenize(input)?)?);
+        assert_eq!(
+            warnings,
+            vec![MovaWarning::UnreachableCode("let y = (x + 1)".into())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_does_not_warn_across_branches_of_if() -> Result<()> {
+        let input = "
+            fn sign(x) = {
+                if x < 0 { return 0 - 1 } else { return 1 }
+            }
+            fn clamp(x) = {
+                if x > 9 { return 9 }
+                x
+            }
+        ";
+        assert!(unreachable_code(&parse(tokenize(input)?)?).is_empty());
+
+        let input = "fn f(x) = { if x > 9 { return 9 } else { return x } \n x }";
+        assert_eq!(unreachable_code(&parse(tokenize(input)?)?).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_back_doc_comments() -> Result<()> {
+        let input = "
+            /// Adds two numbers
+            /// and returns the sum
+            fn add(a, b) = a + b
+            // Not documentation
+            fn sub(a, b) = a - b
+            {
+                /// Twice the number
+                fn double(x) = x * 2
+            }
+        ";
+        let program =
+            parse_with_spans(tokenize_with_spans(input, true)?, ParserOptions::default())?;
+
+        let docs = docs(&program);
+        let docs: Vec<(&str, &str)> = docs
+            .iter()
+            .map(|(name, doc)| (name.as_str(), doc.as_ref()))
+            .collect();
+        assert_eq!(
+            docs,
+            vec![
+                ("add", "Adds two numbers\nand returns the sum"),
+                ("double", "Twice the number")
+            ]
+        );
+        Ok(())
+    }
+}
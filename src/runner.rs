@@ -1,9 +1,314 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, io::Write, rc::Rc};
 
-use crate::{error::Result, interpreter::*, lexer::tokenize, parser::parse};
+use crate::{
+    error::Result,
+    interpreter::*,
+    lexer::tokenize_with_spans,
+    parser::{
+        ParserOptions, expression::Expression, node::Node, parse_statements, parse_with_spans,
+    },
+};
 
+/// Runs a program and returns the value of its last node, or `None` if that node is a
+/// statement, like in a block. An empty program and an empty block also yield `None`.
 pub fn run(input: &str) -> Result<Option<Value>> {
-    let tokens = tokenize(input)?;
-    let program = parse(tokens)?;
-    evaluate(Rc::new(program), Rc::new(RefCell::new(Scope::new(None))))
+    run_with(input, &Config::default())
+}
+
+pub fn run_with(input: &str, config: &Config) -> Result<Option<Value>> {
+    run_in(input, Rc::new(RefCell::new(Scope::new(None))), config)
+}
+
+/// Like `run_with`, but also reports a failure to `diagnostics`
+pub fn run_with_diagnostics(
+    input: &str,
+    config: &Config,
+    diagnostics: &mut impl Write,
+) -> Result<Option<Value>> {
+    let result = run_with(input, config);
+    if let Err(e) = &result {
+        // A diagnostic that cannot be written must not hide the error itself
+        let _ = writeln!(diagnostics, "{e}");
+    }
+    result
+}
+
+/// Like `run_with`, but parses and evaluates one top-level statement at a time,
+/// so only a single statement's syntax tree is held in memory.
+///
+/// Statements before a syntax error have already run by the time it is reported.
+pub fn run_streaming(input: &str, config: &Config) -> Result<Option<Value>> {
+    config.start_run();
+    let scope = Rc::new(RefCell::new(Scope::new(None)));
+    let tokens = tokenize_with_spans(input, false)?;
+
+    let mut result = Ok(None);
+    for statement in parse_statements(tokens, ParserOptions::default()) {
+        result = statement.and_then(|node| evaluate_with(&node, Rc::clone(&scope), config));
+        if result.is_err() {
+            break;
+        }
+    }
+
+    result
+}
+
+/// Like `run`, but returns the result of every top-level node in order, as a notebook or
+/// REPL transcript would show them. Statements contribute `None`.
+pub fn run_all(input: &str, config: &Config) -> Result<Vec<Option<Value>>> {
+    config.start_run();
+    let tokens = tokenize_with_spans(input, false)?;
+    let program = parse_with_spans(tokens, ParserOptions::default())?;
+    let nodes = match &program {
+        Node::Expression(expression) => match &**expression {
+            Expression::Program(nodes) => nodes.as_ref(),
+            _ => std::slice::from_ref(&program),
+        },
+        Node::Statement(_) => std::slice::from_ref(&program),
+    };
+
+    let scope = Rc::new(RefCell::new(Scope::new(None)));
+    nodes
+        .iter()
+        .map(|node| evaluate_with(node, Rc::clone(&scope), config))
+        .collect()
+}
+
+fn run_in(input: &str, scope: Rc<RefCell<Scope>>, config: &Config) -> Result<Option<Value>> {
+    config.start_run();
+    let tokens = tokenize_with_spans(input, false)?;
+    let program = parse_with_spans(tokens, ParserOptions::default())?;
+    evaluate_with(&program, scope, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_runs_boolean_literals() -> Result<()> {
+        assert_eq!(run("true")?, Some(Value::Boolean(true)));
+        assert_eq!(run("false")?, Some(Value::Boolean(false)));
+        Ok(())
+    }
+
+    #[test]
+    fn it_uses_boolean_literal_as_condition() -> Result<()> {
+        assert_eq!(run("if true { 1 } else { 2 }")?, Some(Value::Number(1)));
+        assert_eq!(run("if false { 1 } else { 2 }")?, Some(Value::Number(2)));
+        assert_eq!(
+            run("let flag = 1 < 2; if flag { true } else { false }")?,
+            Some(Value::Boolean(true))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_binds_comparison_passed_as_argument() -> Result<()> {
+        let input = "
+            fn pick(condition, a, b) = if condition { a } else { b }
+            let x = 1
+            let y = 2
+            pick(x < y, 10, 20) + pick(x > y, 100, 200)
+        ";
+        assert_eq!(run(input)?, Some(Value::Number(210)));
+        Ok(())
+    }
+
+    #[test]
+    fn it_binds_conjunction_passed_as_argument() -> Result<()> {
+        let input = "
+            fn id(flag) = flag
+            let x = 1
+            let y = 2
+            id(x < y && y < 3 && x == 1)
+        ";
+        assert_eq!(run(input)?, Some(Value::Boolean(true)));
+        assert_eq!(
+            run("fn id(flag) = flag; id(1 < 2 && 2 < 1)")?,
+            Some(Value::Boolean(false))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_short_circuits_conjunction() -> Result<()> {
+        assert_eq!(run("false && missing()")?, Some(Value::Boolean(false)));
+        Ok(())
+    }
+
+    #[test]
+    fn it_still_parses_double_borrow() -> Result<()> {
+        assert_eq!(run("let x = 7; let r = &&x; **r")?, Some(Value::Number(7)));
+        Ok(())
+    }
+
+    #[test]
+    fn it_reclaims_scope_of_recursive_function() -> Result<()> {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let weak = Rc::downgrade(&scope);
+        let input = "
+            fn count(n) = if n < 1 { 0 } else { 1 + count(n - 1) }
+            count(3)
+        ";
+        assert_eq!(
+            run_in(input, scope, &Config::default())?,
+            Some(Value::Number(3))
+        );
+        assert!(weak.upgrade().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn it_reclaims_scope_after_error_in_block() {
+        let scope = Rc::new(RefCell::new(Scope::new(None)));
+        let weak = Rc::downgrade(&scope);
+        let input = "{ fn f() = 1; 1 / 0 }";
+        assert!(run_in(input, scope, &Config::default()).is_err());
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn it_calls_function_returned_from_another() -> Result<()> {
+        let input = "
+            let base = 10;
+            fn make() = { fn add(x) = x + base; add };
+            let f = make();
+            f(1)
+        ";
+        assert_eq!(run(input)?, Some(Value::Number(11)));
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_returned_reference_valid() -> Result<()> {
+        let result = run("let x = 5; &x")?.unwrap();
+        assert_eq!(result.to_string(), "&5");
+        Ok(())
+    }
+
+    #[test]
+    fn it_writes_output_and_diagnostics_to_given_streams() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let config = Config {
+            output: Output(output.clone()),
+            ..Config::default()
+        };
+        let mut diagnostics = Vec::new();
+
+        let result = run_with_diagnostics("print(1 + 2, true); missing", &config, &mut diagnostics);
+        assert!(result.is_err());
+        assert_eq!(String::from_utf8_lossy(&output.borrow()), "3 true\n");
+        assert_eq!(
+            String::from_utf8_lossy(&diagnostics),
+            "Runtime error: Unable to resolve missing\n"
+        );
+    }
+
+    #[test]
+    fn it_reads_lines_from_given_input() {
+        let config = Config {
+            input: Input(Rc::new(RefCell::new(std::io::Cursor::new(
+                "first\nsecond\r\n",
+            )))),
+            ..Config::default()
+        };
+
+        let result = run_with("let a = input(); let b = input(); (a, b)", &config).unwrap();
+        assert_eq!(result.unwrap().to_string(), "(first, second)");
+        assert_eq!(
+            run_with("input()", &config).unwrap_err().to_string(),
+            "Runtime error: Reached the end of input"
+        );
+    }
+
+    #[test]
+    fn it_streams_statements_like_batch_run() -> Result<()> {
+        let input = "
+            fn add(a, b) = a + b
+            let mut total = 0
+            let (x, y) = (3, 4)
+            while total < 20 {
+                total = add(total, x * y)
+            }
+            let r = &total;
+            *r + 1
+        ";
+        let config = Config::default();
+        assert_eq!(run_streaming(input, &config)?, run(input)?);
+        assert_eq!(run_streaming(input, &config)?, Some(Value::Number(25)));
+        assert_eq!(run_streaming("", &config)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn it_runs_statements_before_syntax_error_when_streaming() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let config = Config {
+            output: Output(output.clone()),
+            ..Config::default()
+        };
+
+        assert!(run_streaming("print(1)\nlet = 2\nprint(3)", &config).is_err());
+        assert_eq!(String::from_utf8_lossy(&output.borrow()), "1\n");
+    }
+
+    #[test]
+    fn it_runs_unary_minus() -> Result<()> {
+        assert_eq!(run("-5")?, Some(Value::Number(-5)));
+        assert_eq!(run("3 - 5")?, Some(Value::Number(-2)));
+        assert_eq!(run("- -3")?, Some(Value::Number(3)));
+        assert_eq!(run("2 * -3")?, Some(Value::Number(-6)));
+        assert_eq!(run("-2 * 3 - -1")?, Some(Value::Number(-5)));
+        assert_eq!(run("let x = 4; let r = &x; -r")?, Some(Value::Number(-4)));
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_invalid_negation() {
+        assert_eq!(
+            run("-true").unwrap_err().to_string(),
+            "Runtime error: Operator '-' is not defined for boolean"
+        );
+        assert!(run("let min = -2147483647 - 1; -min").is_err());
+    }
+
+    #[test]
+    fn it_yields_no_value_for_empty_programs_and_blocks() -> Result<()> {
+        assert_eq!(run("")?, None);
+        assert_eq!(run("// only a comment")?, None);
+        assert_eq!(run("{}")?, run("")?);
+        assert_eq!(run("{ {} }")?, None);
+        assert_eq!(run_streaming("", &Config::default())?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn it_yields_no_value_for_blocks_of_statements() -> Result<()> {
+        assert_eq!(run("{ let x = 1; }")?, None);
+        assert_eq!(run("{ let x = 1; let y = x; }")?, None);
+        assert_eq!(
+            run("let x = { let y = 1; }; x").unwrap_err().to_string(),
+            "Runtime error: Expected expression, but received statement as value"
+        );
+        assert_eq!(run("1; let x = 2;")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn it_returns_the_result_of_every_top_level_node() -> Result<()> {
+        let input = "
+            let x = 2;
+            x * 3;
+            x + 1
+        ";
+        let results = run_all(input, &Config::default())?;
+        assert_eq!(
+            results,
+            vec![None, Some(Value::Number(6)), Some(Value::Number(3))]
+        );
+        assert_eq!(run_all("", &Config::default())?, vec![]);
+        assert!(run_all("1; missing; 2", &Config::default()).is_err());
+        Ok(())
+    }
 }
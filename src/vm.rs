@@ -0,0 +1,516 @@
+use std::mem;
+
+use crate::{
+    compiler::{Bytecode, Function, Instruction, Variable},
+    error::{MovaError, Result, RuntimeError},
+    interpreter::{Config, Value, apply_unary, evaluate_binary_expression},
+};
+
+/// How many calls may be nested before execution fails, since frames are not limited by the
+/// native stack like in the tree-walking interpreter
+const MAX_CALL_DEPTH: usize = 100_000;
+
+/// A variable's value and whether `let mut` declared it
+#[derive(Debug, Clone)]
+struct Binding {
+    value: Value,
+    is_mutable: bool,
+}
+
+struct Frame<'a> {
+    function: &'a Function,
+    ip: usize,
+    locals: Vec<Option<Binding>>,
+}
+
+struct Machine<'a> {
+    bytecode: &'a Bytecode,
+    config: &'a Config,
+    /// `None` until the top-level `let` or block declaring the variable has run
+    globals: Vec<Option<Binding>>,
+    /// Whether each function's declaration has run
+    defined: Vec<bool>,
+    stack: Vec<Option<Value>>,
+    frame: Frame<'a>,
+    callers: Vec<Frame<'a>>,
+}
+
+/// Runs compiled bytecode, producing the same result as evaluating the program it was
+/// compiled from
+pub fn execute(bytecode: &Bytecode, config: &Config) -> Result<Option<Value>> {
+    config.start_run();
+    let mut machine = Machine {
+        bytecode,
+        config,
+        globals: vec![None; bytecode.globals.len()],
+        defined: vec![false; bytecode.functions.len()],
+        stack: Vec::new(),
+        frame: Frame {
+            function: &bytecode.main,
+            ip: 0,
+            locals: Vec::new(),
+        },
+        callers: Vec::new(),
+    };
+
+    machine.run().map_err(|error| {
+        // Like the tree-walker, name the functions that were being called, outermost first
+        let Machine { frame, callers, .. } = &machine;
+        std::iter::once(frame)
+            .chain(callers.iter().rev())
+            .take(callers.len())
+            .fold(error, |error, frame| error.in_frame(&frame.function.name))
+    })
+}
+
+impl<'a> Machine<'a> {
+    fn run(&mut self) -> Result<Option<Value>> {
+        loop {
+            let function: &'a Function = self.frame.function;
+            let instruction = &function.code[self.frame.ip];
+            self.frame.ip += 1;
+
+            match instruction {
+                Instruction::Constant(value) => self.stack.push(Some(value.clone())),
+                Instruction::Nothing => self.stack.push(None),
+                Instruction::Pop => {
+                    self.stack.pop();
+                }
+                Instruction::Duplicate => {
+                    let top = self.stack.last().cloned().flatten();
+                    // Only a copied value can be stored in more than one variable
+                    if let Some(value) = top.as_ref().filter(|value| !value.is_copy()) {
+                        return Err(MovaError::Runtime(
+                            RuntimeError::CannotAssignToSeveralVariables(value.to_string()),
+                        ));
+                    }
+                    self.stack.push(top);
+                }
+                Instruction::Load(variable) => {
+                    let value = self.load(*variable)?;
+                    self.stack.push(Some(value));
+                }
+                Instruction::Declare {
+                    variable,
+                    is_mutable,
+                } => {
+                    let value = self.pop(RuntimeError::ExpectedExpressionAsValue)?;
+                    *self.slot(*variable) = Some(Binding {
+                        value,
+                        is_mutable: *is_mutable,
+                    });
+                }
+                Instruction::Store(variable) => {
+                    let value = self.pop(RuntimeError::ExpectedExpressionAsValue)?;
+                    self.store(*variable, value)?;
+                }
+                Instruction::Unary(operator) => {
+                    let value = self.pop(RuntimeError::ExpectedExpressionAsValue)?;
+                    let result = apply_unary(operator, value, self.config)?;
+                    self.stack.push(Some(result));
+                }
+                Instruction::Binary(operator) => {
+                    let right = self.stack.pop().flatten();
+                    let left = self.pop(RuntimeError::ExpectedExpressionAsLeftOperand)?;
+                    let right = right.ok_or(MovaError::Runtime(
+                        RuntimeError::ExpectedExpressionAsRightOperand,
+                    ))?;
+                    let result = evaluate_binary_expression(operator, left, right, self.config)?;
+                    self.stack.push(Some(result));
+                }
+                Instruction::Jump(target) => self.frame.ip = *target,
+                Instruction::JumpIfFalse(target) => match self.stack.pop().flatten() {
+                    Some(Value::Boolean(true)) => {}
+                    Some(Value::Boolean(false)) => self.frame.ip = *target,
+                    Some(_) => {
+                        return Err(MovaError::Runtime(RuntimeError::ConditionMustBeBoolean));
+                    }
+                    None => return Err(MovaError::Runtime(RuntimeError::ConditionYieldedNoValue)),
+                },
+                Instruction::SkipIfFalse(target) => {
+                    if let Some(Some(Value::Boolean(false))) = self.stack.last() {
+                        self.frame.ip = *target;
+                    }
+                }
+                Instruction::Iterate => {
+                    self.config.check_interrupt()?;
+                    self.config.consume_iteration()?;
+                }
+                Instruction::Define(function) => self.defined[*function] = true,
+                Instruction::Call {
+                    function,
+                    arguments,
+                } => self.call(*function, *arguments)?,
+                Instruction::CallNative { native, arguments } => {
+                    self.config.check_interrupt()?;
+                    let arguments = self.arguments(*arguments)?;
+                    let result = self.bytecode.natives[*native].call(&arguments)?;
+                    self.stack.push(Some(result));
+                }
+                Instruction::Print(arguments) => {
+                    self.config.check_interrupt()?;
+                    let line: Vec<String> = self
+                        .arguments(*arguments)?
+                        .iter()
+                        .map(Value::to_string)
+                        .collect();
+                    writeln!(self.config.output.0.borrow_mut(), "{}", line.join(" ")).map_err(
+                        |e| MovaError::Runtime(RuntimeError::UnableToWriteOutput(e.to_string())),
+                    )?;
+                    self.stack.push(None);
+                }
+                Instruction::Undefined(name) => {
+                    return Err(MovaError::Runtime(RuntimeError::CallToUndefinedFunction(
+                        name.to_string(),
+                    )));
+                }
+                Instruction::Return => {
+                    let result = self.stack.pop().flatten();
+                    match self.callers.pop() {
+                        Some(caller) => {
+                            self.frame = caller;
+                            self.stack.push(result);
+                        }
+                        None => return Ok(result),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pops a value, failing with `error` if the top of the stack is the result of a statement
+    fn pop(&mut self, error: RuntimeError) -> Result<Value> {
+        self.stack.pop().flatten().ok_or(MovaError::Runtime(error))
+    }
+
+    /// Pops the arguments of a call, which were pushed from left to right
+    fn arguments(&mut self, count: usize) -> Result<Vec<Value>> {
+        let start = self.stack.len() - count;
+        self.stack
+            .drain(start..)
+            .map(|argument| {
+                argument.ok_or(MovaError::Runtime(
+                    RuntimeError::ExpectedExpressionAsArgument,
+                ))
+            })
+            .collect()
+    }
+
+    fn call(&mut self, index: usize, count: usize) -> Result<()> {
+        let function = &self.bytecode.functions[index];
+        if !self.defined[index] {
+            return Err(MovaError::Runtime(RuntimeError::CallToUndefinedFunction(
+                function.name.to_string(),
+            )));
+        }
+        self.config.check_interrupt()?;
+        if count != function.parameters {
+            return Err(MovaError::Runtime(RuntimeError::InvalidArgumentCount {
+                expected: function.parameters,
+                received: count,
+            }));
+        }
+        if self.callers.len() >= MAX_CALL_DEPTH {
+            return Err(MovaError::Runtime(RuntimeError::CallDepthExceeded(
+                MAX_CALL_DEPTH,
+            )));
+        }
+
+        let mut locals = vec![None; function.locals.len()];
+        for (local, value) in locals.iter_mut().zip(self.arguments(count)?) {
+            *local = Some(Binding {
+                value,
+                is_mutable: false,
+            });
+        }
+        let caller = mem::replace(
+            &mut self.frame,
+            Frame {
+                function,
+                ip: 0,
+                locals,
+            },
+        );
+        self.callers.push(caller);
+        Ok(())
+    }
+
+    fn name(&self, variable: Variable) -> String {
+        match variable {
+            Variable::Global(index) => self.bytecode.globals[index].to_string(),
+            Variable::Local(index) => self.frame.function.locals[index].to_string(),
+        }
+    }
+
+    fn slot(&mut self, variable: Variable) -> &mut Option<Binding> {
+        match variable {
+            Variable::Global(index) => &mut self.globals[index],
+            Variable::Local(index) => &mut self.frame.locals[index],
+        }
+    }
+
+    /// Like `Scope::resolve`, copies numbers and booleans but moves any other value out of
+    /// the variable
+    fn load(&mut self, variable: Variable) -> Result<Value> {
+        match self.slot(variable) {
+            Some(Binding {
+                value: Value::Uninitialized,
+                ..
+            }) => Err(MovaError::Runtime(
+                RuntimeError::UseOfPossiblyUninitializedVariable(self.name(variable)),
+            )),
+            Some(Binding {
+                value: Value::Moved,
+                ..
+            }) => Err(MovaError::Runtime(RuntimeError::UnableToUseBecauseMoved(
+                self.name(variable),
+            ))),
+            Some(binding) if binding.value.is_copy() => Ok(binding.value.clone()),
+            Some(binding) => Ok(mem::replace(&mut binding.value, Value::Moved)),
+            None => Err(MovaError::Runtime(RuntimeError::UnableToResolve(
+                self.name(variable),
+            ))),
+        }
+    }
+
+    /// Like in the tree-walker, an immutable variable may still receive its first value late
+    fn store(&mut self, variable: Variable, value: Value) -> Result<()> {
+        match self.slot(variable) {
+            Some(binding) if binding.is_mutable || binding.value == Value::Uninitialized => {
+                binding.value = value;
+                Ok(())
+            }
+            Some(_) => Err(MovaError::Runtime(
+                RuntimeError::CannotAssignToImmutableVariable(self.name(variable)),
+            )),
+            None => Err(MovaError::Runtime(RuntimeError::UnableToResolve(
+                self.name(variable),
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compiler::compile,
+        interpreter::{DivisionMode, Output},
+        lexer::tokenize,
+        parser::parse,
+        runner::run_with,
+    };
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        time::Instant,
+    };
+
+    fn execute_source(input: &str, config: &Config) -> Result<Option<Value>> {
+        execute(&compile(&parse(tokenize(input)?)?)?, config)
+    }
+
+    /// Runs the program with both interpreters, expecting the same value or error message
+    fn assert_same_with(input: &str, config: &Config) {
+        match (run_with(input, config), execute_source(input, config)) {
+            (Ok(expected), Ok(actual)) => assert_eq!(expected, actual, "{input}"),
+            (Err(expected), Err(actual)) => {
+                assert_eq!(expected.to_string(), actual.to_string(), "{input}")
+            }
+            (expected, actual) => panic!("{input}: expected {expected:?}, got {actual:?}"),
+        }
+    }
+
+    fn assert_same(input: &str) {
+        assert_same_with(input, &Config::default());
+    }
+
+    const FIBONACCI: &str = "fn fib(n) = if n < 2 { n } else { fib(n - 1) + fib(n - 2) }; ";
+
+    #[test]
+    fn it_matches_the_tree_walker_on_arithmetic() {
+        let inputs = [
+            "1 + 2 * 3",
+            "(1 + 2) * 3 - 4 / 2",
+            "-7 % 3",
+            "~5 ^ 3 | 8 & 12",
+            "1 << 4 >> 2",
+            "1 < 2 && 3 > 2",
+            "1 == 2 != true",
+            "false && 1 / 0 == 0",
+            "7 / 0",
+            "2147483647 + 1",
+            "1 << 40",
+            "true + 1",
+            "-true",
+        ];
+        for input in inputs {
+            assert_same(input);
+        }
+    }
+
+    #[test]
+    fn it_parses_operators_like_the_tree_walker_regardless_of_spacing() -> Result<()> {
+        let cases = [
+            ("let n = 5; n -1", 4),
+            ("fn f(n) = n -1; f(5)", 4),
+            ("let a = 3; 3 -a", 0),
+            ("let a = 3; a *a", 9),
+        ];
+        for (input, expected) in cases {
+            assert_same(input);
+            assert_eq!(
+                run_with(input, &Config::default())?,
+                Some(Value::Number(expected))
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_matches_the_tree_walker_on_variables() {
+        let inputs = [
+            "let x = 1; let y = x + 1; y * 2",
+            "let x = 1; let x = x + 1; x",
+            "let mut x = 1; x = x + 1; x",
+            "let x = 1; x = 2",
+            "let x; x = 5; x",
+            "let x; x",
+            "x",
+            "let mut a = 0; let mut b = 0; a = b = 3; a + b",
+            "let x = 1; let y = { let x = 10; x + 1 }; x + y",
+            "{ let y = 1 }; y",
+            "let x = 1; do x + 1",
+            "let x = 1;",
+            "",
+            "{}",
+            "{ let x = 1; }",
+        ];
+        for input in inputs {
+            assert_same(input);
+        }
+    }
+
+    #[test]
+    fn it_matches_the_tree_walker_on_conditionals() {
+        let inputs = [
+            "if 1 < 2 { 10 } else { 20 }",
+            "if 1 > 2 { 10 } else if 2 > 1 { 20 } else { 30 }",
+            "if false { 1 }",
+            "if 1 { 2 }",
+            "if { let x = 1; } { 2 }",
+            "let mut i = 0; let mut s = 0; while i < 10 { s = s + i; i = i + 1; s }",
+            "while false { 1 }",
+        ];
+        for input in inputs {
+            assert_same(input);
+        }
+    }
+
+    #[test]
+    fn it_matches_the_tree_walker_on_calls() {
+        let inputs = [
+            "fn add(a, b) = a + b; add(1, add(2, 3))",
+            &format!("{FIBONACCI}fib(15)"),
+            "fn f() = x * 2; let x = 21; f()",
+            "fn even(n) = if n == 0 { true } else { odd(n - 1) }; fn odd(n) = if n == 0 { false } else { even(n - 1) }; even(10)",
+            "fn f(x) = { let y = x * 2; { let x = y + 1; x } }; f(3)",
+            "fn f(x) = x; f(1, 2)",
+            "fn f(x) = x / 0; fn g(x) = f(x) + 1; g(1)",
+            "fn f(x) = { x = 2; x }; f(1)",
+            "f(); fn f() = 1",
+            "missing(1)",
+            "max(3, 9, 4) + compare(1, 2)",
+            "max()",
+            "fn max(a, b) = a; max(1, 2)",
+            "let s = to_string(1); let t = s; s",
+            "let s = to_string(1); let t = s; t",
+            "fn f() = to_string(1); let s = f(); let t = s; s",
+            "fn f(s) = s; let s = to_string(1); f(s); s",
+            "let mut s = to_string(1); let t = s; s = to_string(2); s",
+            "let mut a = 0; let mut b = 0; a = b = to_string(1); a",
+        ];
+        for input in inputs {
+            assert_same(input);
+        }
+    }
+
+    #[test]
+    fn it_prints_like_the_tree_walker() {
+        let input = "fn show(x) = print(x, x > 1); show(1); show(2); print()";
+        let outputs: Vec<String> = (0..2)
+            .map(|run| {
+                let buffer = Rc::new(RefCell::new(Vec::new()));
+                let config = Config {
+                    output: Output(buffer.clone()),
+                    ..Config::default()
+                };
+                let result = if run == 0 {
+                    run_with(input, &config)
+                } else {
+                    execute_source(input, &config)
+                };
+                assert_eq!(result.unwrap(), None);
+                String::from_utf8(buffer.borrow().clone()).unwrap()
+            })
+            .collect();
+        assert_eq!(outputs[0], "1 false\n2 true\n\n");
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn it_respects_the_config() {
+        let floor = Config {
+            division: DivisionMode::Floor,
+            ..Config::default()
+        };
+        assert_same_with("-7 / 2", &floor);
+
+        let budget = Config {
+            iteration_budget: Some(Cell::new(5)),
+            ..Config::default()
+        };
+        let result = execute_source("let mut i = 0; while true { i = i + 1 }", &budget);
+        assert!(matches!(
+            result,
+            Err(MovaError::Runtime(RuntimeError::IterationLimitExceeded))
+        ));
+    }
+
+    #[test]
+    fn it_recurses_without_using_the_native_stack() -> Result<()> {
+        let count = "fn count(n) = if n == 0 { 0 } else { 1 + count(n - 1) }; ";
+        let result = execute_source(&format!("{count}count(50000)"), &Config::default())?;
+        assert_eq!(result, Some(Value::Number(50000)));
+
+        let result = execute_source("fn f(n) = f(n + 1); f(0)", &Config::default());
+        assert!(matches!(
+            result,
+            Err(MovaError::Backtrace { error, .. })
+                if matches!(*error, MovaError::Runtime(RuntimeError::CallDepthExceeded(_)))
+        ));
+        Ok(())
+    }
+
+    /// Prints the time each interpreter takes; run with
+    /// `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_fibonacci() -> Result<()> {
+        let input = format!("{FIBONACCI}fib(25)");
+        let config = Config::default();
+
+        let start = Instant::now();
+        let expected = run_with(&input, &config)?;
+        let tree_walker = start.elapsed();
+
+        let bytecode = compile(&parse(tokenize(&input)?)?)?;
+        let start = Instant::now();
+        let actual = execute(&bytecode, &config)?;
+        let vm = start.elapsed();
+
+        assert_eq!(expected, actual);
+        println!("fib(25): tree-walker {tree_walker:?}, vm {vm:?}");
+        Ok(())
+    }
+}